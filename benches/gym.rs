@@ -0,0 +1,77 @@
+//强化学习环境的吞吐基准：逐级增大环境数，测量VecEnv并行推进与逐个顺序推进每秒的步数。
+//运行：cargo bench --bench gym
+use rand::prng::XorShiftRng;
+use rand::{Rng, SeedableRng};
+use russia_block::gym::{ActionSpace, Env, VecEnv};
+use std::time::Instant;
+
+const SIZES: [usize; 4] = [16, 256, 1024, 4096];
+//每种规模推进的总步数(环境数×轮数)；落点级每步要枚举全部落点，少推进一些
+const KEYPRESS_STEPS: usize = 2_000_000;
+const PLACEMENT_STEPS: usize = 100_000;
+
+fn steps_per_sec(start: Instant, steps: usize) -> f64 {
+    steps as f64 / start.elapsed().as_secs_f64()
+}
+
+fn sequential(space: ActionSpace, count: usize, rounds: usize) -> f64 {
+    let mut rng = XorShiftRng::seed_from_u64(count as u64);
+    let mut envs: Vec<Env> = (0..count).map(|_| Env::new(space)).collect();
+    let mut actions: Vec<usize> = envs.iter().map(Env::action_count).collect();
+    let start = Instant::now();
+    for _ in 0..rounds {
+        for (i, env) in envs.iter_mut().enumerate() {
+            let (observation, _, done) = env.step(rng.gen_range(0, actions[i])).unwrap();
+            actions[i] = match done {
+                true => env.reset(i as u64).actions,
+                false => observation.actions,
+            };
+        }
+    }
+    steps_per_sec(start, count * rounds)
+}
+
+fn batched(space: ActionSpace, count: usize, rounds: usize) -> f64 {
+    let mut rng = XorShiftRng::seed_from_u64(count as u64);
+    let mut envs = VecEnv::new(count, space);
+    let mut counts: Vec<usize> = envs.reset(0).iter().map(|o| o.actions).collect();
+    let mut actions = vec![0; count];
+    let start = Instant::now();
+    for _ in 0..rounds {
+        for (action, &count) in actions.iter_mut().zip(counts.iter()) {
+            *action = rng.gen_range(0, count);
+        }
+        counts = envs
+            .step(&actions)
+            .unwrap()
+            .iter()
+            .map(|(observation, _, _)| observation.actions)
+            .collect();
+    }
+    steps_per_sec(start, count * rounds)
+}
+
+fn main() {
+    println!(
+        "{:>10} {:>10} {:>14} {:>14} {:>8}",
+        "space", "envs", "seq steps/s", "vec steps/s", "speedup"
+    );
+    for (space, total) in [
+        (ActionSpace::Keypress, KEYPRESS_STEPS),
+        (ActionSpace::Placement, PLACEMENT_STEPS),
+    ] {
+        for &count in SIZES.iter() {
+            let rounds = (total / count).max(1);
+            let seq = sequential(space, count, rounds);
+            let vec = batched(space, count, rounds);
+            println!(
+                "{:>10} {:>10} {:>14.0} {:>14.0} {:>8.2}",
+                format!("{:?}", space),
+                count,
+                seq,
+                vec,
+                vec / seq
+            );
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//排行榜名次统计的基准：逐级增大条目数，测量插入、求名次与按名次取值的平均耗时，
+//并与按分数有序的Vec(二分查找加插入)对照。运行：cargo bench --bench rank
+use rand::prng::XorShiftRng;
+use rand::{Rng, SeedableRng};
+use russia_block::ranking::RankTree;
+use std::hint::black_box;
+use std::time::Instant;
+
+const SIZES: [usize; 4] = [10_000, 100_000, 1_000_000, 4_000_000];
+//有序Vec的插入为O(n)，只测到这个规模
+const VEC_LIMIT: usize = 100_000;
+const QUERIES: usize = 100_000;
+//分数取值范围，贴近实际对局的得分
+const MAX_SCORE: u32 = 20_000;
+
+fn per_op(start: Instant, ops: usize) -> f64 {
+    start.elapsed().as_nanos() as f64 / ops as f64
+}
+
+fn main() {
+    println!(
+        "{:>10} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "entries", "insert ns", "rank ns", "get ns", "vec ins ns", "vec rank ns"
+    );
+    for &n in SIZES.iter() {
+        let mut rng = XorShiftRng::seed_from_u64(n as u64);
+        let scores: Vec<u32> = (0..n).map(|_| rng.gen_range(0, MAX_SCORE)).collect();
+        let queries: Vec<u32> = (0..QUERIES).map(|_| rng.gen_range(0, MAX_SCORE)).collect();
+
+        let start = Instant::now();
+        let mut tree = RankTree::new();
+        for (i, &score) in scores.iter().enumerate() {
+            tree.insert(score, i);
+        }
+        let insert = per_op(start, n);
+
+        let start = Instant::now();
+        for &score in queries.iter() {
+            black_box(tree.rank(score));
+        }
+        let rank = per_op(start, QUERIES);
+
+        let start = Instant::now();
+        for i in 0..QUERIES {
+            black_box(tree.get(i * 7919 % n));
+        }
+        let get = per_op(start, QUERIES);
+
+        let (vec_insert, vec_rank) = if n <= VEC_LIMIT {
+            let start = Instant::now();
+            let mut sorted: Vec<u32> = Vec::new();
+            for &score in scores.iter() {
+                let at = sorted.partition_point(|&s| s >= score);
+                sorted.insert(at, score);
+            }
+            let insert = per_op(start, n);
+            let start = Instant::now();
+            for &score in queries.iter() {
+                black_box(sorted.partition_point(|&s| s > score));
+            }
+            (
+                format!("{:.0}", insert),
+                format!("{:.0}", per_op(start, QUERIES)),
+            )
+        } else {
+            ("-".to_string(), "-".to_string())
+        };
+
+        //与有序Vec核对名次
+        let mut sorted = scores.clone();
+        sorted.sort_unstable_by(|a, b| b.cmp(a));
+        for &score in queries.iter().take(1000) {
+            assert_eq!(tree.rank(score), sorted.partition_point(|&s| s > score));
+        }
+        assert_eq!(tree.get(n / 2).map(|(score, _)| score), Some(sorted[n / 2]));
+
+        println!(
+            "{:>10} {:>12.0} {:>12.0} {:>12.0} {:>12} {:>12}",
+            n, insert, rank, get, vec_insert, vec_rank
+        );
+    }
+}
@@ -1,5 +1,4 @@
-fn main()->Result<(),Box<dyn std::error::Error>>
-{
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::compile_protos("proto/rblock.proto")?;
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,185 @@
+//会话令牌："player_id.过期时间.HMAC-SHA256签名"。拦截器校验请求中的令牌，
+//通过后把调用者放进请求扩展；不带令牌的请求按匿名处理，由各接口决定是否允许
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::{Request, Status};
+
+use crate::storage;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const TOKEN_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const KEY_LEN: usize = 32;
+
+//通过令牌校验的调用者
+#[derive(Clone, Debug)]
+pub struct AuthPlayer {
+    pub player_id: String,
+}
+
+#[derive(Clone)]
+pub struct TokenKey {
+    key: Arc<Vec<u8>>,
+}
+
+impl TokenKey {
+    //只在内存中，重启后已发出的令牌全部失效
+    pub fn random() -> Self {
+        TokenKey {
+            key: Arc::new((0..KEY_LEN).map(|_| rand::random()).collect()),
+        }
+    }
+
+    //密钥文件不存在时生成，只允许本人读写；重启后已发出的令牌仍然有效
+    pub fn load_or_create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        match std::fs::read(path) {
+            Ok(key) if key.len() >= KEY_LEN => Ok(TokenKey { key: Arc::new(key) }),
+            Ok(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is shorter than {} bytes", path.display(), KEY_LEN),
+            )),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let key = TokenKey::random();
+                //create_new：不覆盖同时被别的进程建好的密钥
+                let mut options = std::fs::OpenOptions::new();
+                options.write(true).create_new(true);
+                #[cfg(unix)]
+                std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+                options.open(path)?.write_all(&key.key)?;
+                Ok(key)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn mac(&self, payload: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).unwrap();
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    //返回(令牌, 过期时间)
+    pub fn issue(&self, player_id: &str) -> (String, u64) {
+        let expires_at = storage::unix_now() + TOKEN_TTL.as_secs();
+        let payload = format!("{}.{}", player_id, expires_at);
+        let signature = hex::encode(self.mac(&payload).finalize().into_bytes());
+        (format!("{}.{}", payload, signature), expires_at)
+    }
+
+    //校验签名与过期时间，返回令牌所属的player_id
+    pub fn verify(&self, token: &str) -> Result<String, &'static str> {
+        let (payload, signature) = token.rsplit_once('.').ok_or("malformed token")?;
+        let signature = hex::decode(signature).map_err(|_| "malformed token")?;
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| "invalid token")?;
+        let (player_id, expires_at) = payload.rsplit_once('.').ok_or("malformed token")?;
+        let expires_at: u64 = expires_at.parse().map_err(|_| "malformed token")?;
+        if expires_at <= storage::unix_now() {
+            return Err("token expired");
+        }
+        Ok(player_id.to_string())
+    }
+}
+
+//带令牌的请求必须通过校验，否则直接拒绝；签名由tonic规定
+#[allow(clippy::result_large_err)]
+pub fn interceptor(
+    key: TokenKey,
+) -> impl FnMut(Request<()>) -> Result<Request<()>, Status> + Clone {
+    move |mut request: Request<()>| {
+        let header = match request.metadata().get("authorization") {
+            Some(header) => header,
+            None => return Ok(request),
+        };
+        let token = header
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("malformed authorization header"))?;
+        let player_id = key.verify(token).map_err(Status::unauthenticated)?;
+        request.extensions_mut().insert(AuthPlayer { player_id });
+        Ok(request)
+    }
+}
+
+//请求的调用者，匿名时为None
+pub fn caller<T>(request: &Request<T>) -> Option<String> {
+    request
+        .extensions()
+        .get::<AuthPlayer>()
+        .map(|auth| auth.player_id.clone())
+}
+
+pub fn new_secret() -> String {
+    format!(
+        "{:016x}{:016x}",
+        rand::random::<u64>(),
+        rand::random::<u64>()
+    )
+}
+
+pub fn hash_secret(secret: &str) -> Vec<u8> {
+    Sha256::digest(secret.as_bytes()).to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_file_is_private_and_reloaded() {
+        let path = std::env::temp_dir().join(format!("rblock-auth-{}.key", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let created = TokenKey::load_or_create(&path).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let loaded = TokenKey::load_or_create(&path).unwrap();
+        assert_eq!(created.key, loaded.key);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn token_round_trip() {
+        let key = TokenKey::random();
+        let (token, expires_at) = key.issue("p1.x");
+        assert!(expires_at > storage::unix_now());
+        //player_id中含有'.'也能还原
+        assert_eq!(key.verify(&token), Ok("p1.x".to_string()));
+        assert_eq!(TokenKey::random().verify(&token), Err("invalid token"));
+    }
+
+    #[test]
+    fn tampered_token_is_rejected() {
+        let key = TokenKey::random();
+        let (token, _) = key.issue("p1");
+        let forged = token.replacen("p1", "p2", 1);
+        assert_eq!(key.verify(&forged), Err("invalid token"));
+        //去掉签名后过期时间被当成签名
+        let (payload, _) = token.rsplit_once('.').unwrap();
+        assert_eq!(key.verify(payload), Err("invalid token"));
+        assert_eq!(
+            key.verify(&format!("{}.zz", payload)),
+            Err("malformed token")
+        );
+        assert_eq!(key.verify("p1"), Err("malformed token"));
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let key = TokenKey::random();
+        let payload = format!("p1.{}", storage::unix_now() - 1);
+        let signature = hex::encode(key.mac(&payload).finalize().into_bytes());
+        let token = format!("{}.{}", payload, signature);
+        assert_eq!(key.verify(&token), Err("token expired"));
+    }
+}
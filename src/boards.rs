@@ -0,0 +1,139 @@
+//按模式与时间段划分的排行榜：每条成绩同时计入所属模式与全部模式的总榜、日榜和周榜。
+//日榜与周榜只保留当期，过期的在计入新成绩或定时清理时丢弃，查询时按当前时间取当期
+use crate::rblock::Period;
+use russia_block::modes::LEADERBOARD_MODES;
+use russia_block::ranking::RankTree;
+use std::collections::HashMap;
+
+const DAY: u64 = 24 * 60 * 60;
+//1970-01-01是周四，加3天使周期从周一开始
+const WEEK_OFFSET_DAYS: u64 = 3;
+const PERIODS: [Period; 3] = [Period::AllTime, Period::Daily, Period::Weekly];
+
+//time所在的周期编号，总榜只有一期
+pub fn window(period: Period, time: u64) -> u64 {
+    match period {
+        Period::AllTime => 0,
+        Period::Daily => time / DAY,
+        Period::Weekly => (time / DAY + WEEK_OFFSET_DAYS) / 7,
+    }
+}
+
+//当期结束的时间，总榜为0
+pub fn resets_at(period: Period, now: u64) -> u64 {
+    match period {
+        Period::AllTime => 0,
+        Period::Daily => (window(period, now) + 1) * DAY,
+        Period::Weekly => ((window(period, now) + 1) * 7 - WEEK_OFFSET_DAYS) * DAY,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct BoardKey {
+    //空为全部模式
+    mode: String,
+    period: Period,
+    window: u64,
+}
+
+//各榜只保存成绩在Store中的序号
+#[derive(Default)]
+pub struct Boards {
+    boards: HashMap<BoardKey, RankTree<usize>>,
+}
+
+impl Boards {
+    //time为成绩计入的时间，不在当期的成绩不计入日榜与周榜
+    pub fn insert(&mut self, index: usize, score: u32, mode: &str, time: u64, now: u64) {
+        let modes = match LEADERBOARD_MODES.contains(&mode) {
+            true => vec!["", mode],
+            false => vec![""],
+        };
+        for period in PERIODS {
+            let current = window(period, now);
+            if window(period, time) != current {
+                continue;
+            }
+            for mode in &modes {
+                let key = BoardKey {
+                    mode: mode.to_string(),
+                    period,
+                    window: current,
+                };
+                self.boards.entry(key).or_default().insert(score, index);
+            }
+        }
+        self.prune(now);
+    }
+
+    //当期的榜，还没有成绩时为None
+    pub fn get(&self, mode: &str, period: Period, now: u64) -> Option<&RankTree<usize>> {
+        self.boards.get(&BoardKey {
+            mode: mode.to_string(),
+            period,
+            window: window(period, now),
+        })
+    }
+
+    //丢弃已过期的日榜与周榜，返回丢弃的数量
+    pub fn prune(&mut self, now: u64) -> usize {
+        let before = self.boards.len();
+        self.boards
+            .retain(|key, _| key.window == window(key.period, now));
+        before - self.boards.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.boards.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //1970-01-05是周一
+    const MONDAY: u64 = 4 * DAY;
+
+    #[test]
+    fn daily_window_turns_at_midnight() {
+        assert_eq!(window(Period::Daily, MONDAY - 1), 3);
+        assert_eq!(window(Period::Daily, MONDAY), 4);
+        assert_eq!(resets_at(Period::Daily, MONDAY - 1), MONDAY);
+        assert_eq!(resets_at(Period::Daily, MONDAY), MONDAY + DAY);
+    }
+
+    #[test]
+    fn weekly_window_turns_on_monday() {
+        assert_eq!(window(Period::Weekly, 0), 0);
+        assert_eq!(window(Period::Weekly, MONDAY - 1), 0);
+        assert_eq!(window(Period::Weekly, MONDAY), 1);
+        assert_eq!(window(Period::Weekly, MONDAY + 7 * DAY - 1), 1);
+        assert_eq!(resets_at(Period::Weekly, 0), MONDAY);
+        assert_eq!(resets_at(Period::Weekly, MONDAY), MONDAY + 7 * DAY);
+        assert_eq!(resets_at(Period::AllTime, MONDAY), 0);
+    }
+
+    #[test]
+    fn stale_boards_are_pruned() {
+        let mut boards = Boards::default();
+        let mode = LEADERBOARD_MODES[0];
+        boards.insert(0, 10, mode, MONDAY, MONDAY);
+        //模式榜与总榜各三期
+        assert_eq!(boards.len(), 6);
+        //过了一天只丢弃日榜
+        assert_eq!(boards.prune(MONDAY + DAY), 2);
+        assert!(boards.get(mode, Period::Daily, MONDAY + DAY).is_none());
+        assert_eq!(
+            boards
+                .get(mode, Period::Weekly, MONDAY + DAY)
+                .unwrap()
+                .len(),
+            1
+        );
+        //上周的成绩只计入总榜
+        boards.insert(1, 20, "", MONDAY, MONDAY + 7 * DAY);
+        assert_eq!(boards.len(), 2);
+        assert_eq!(boards.get("", Period::AllTime, 0).unwrap().len(), 2);
+    }
+}
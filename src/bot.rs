@@ -0,0 +1,241 @@
+//内置AI：枚举当前方块所有旋转与列的落点，用启发式评分挑选最优者，难度决定操作速度与失误率
+use crate::engine::{Cell, Engine, Input, COLS};
+use rand::prng::ChaChaRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+//启发式权重：总高度、消行数、空洞数、相邻列高度差之和
+const HEIGHT_WEIGHT: f64 = -0.510066;
+const LINES_WEIGHT: f64 = 0.760666;
+const HOLES_WEIGHT: f64 = -0.35663;
+const BUMPINESS_WEIGHT: f64 = -0.184483;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn parse(name: &str) -> Option<Difficulty> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+
+    //两次操作之间的间隔
+    pub fn interval(self) -> Duration {
+        match self {
+            Difficulty::Easy => Duration::from_millis(250),
+            Difficulty::Normal => Duration::from_millis(100),
+            Difficulty::Hard => Duration::from_millis(40),
+        }
+    }
+
+    //放弃最优落点、随机选一个落点的概率
+    pub fn mistake_rate(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.2,
+            Difficulty::Normal => 0.05,
+            Difficulty::Hard => 0.0,
+        }
+    }
+}
+
+//一个落点：从当前位置到落定的输入序列、落定的格子(已排序)，以及落定后局面的评分
+#[derive(Clone, Debug)]
+pub struct Placement {
+    pub inputs: Vec<Input>,
+    pub cells: [(i32, i32); 4],
+    pub score: f64,
+}
+
+//棋盘的启发式评分，lines为刚消除的行数
+pub fn evaluate(board: &[[Cell; COLS]], lines: u32) -> f64 {
+    let mut heights = [0; COLS];
+    let mut holes = 0;
+    for (col, height) in heights.iter_mut().enumerate() {
+        *height = board
+            .iter()
+            .rposition(|cells| cells[col] != Cell::Empty)
+            .map_or(0, |row| row + 1);
+        holes += board[..*height]
+            .iter()
+            .filter(|cells| cells[col] == Cell::Empty)
+            .count();
+    }
+    let aggregate: usize = heights.iter().sum();
+    let bumpiness: usize = heights.windows(2).map(|w| w[0].abs_diff(w[1])).sum();
+    HEIGHT_WEIGHT * aggregate as f64
+        + LINES_WEIGHT * lines as f64
+        + HOLES_WEIGHT * holes as f64
+        + BUMPINESS_WEIGHT * bumpiness as f64
+}
+
+//枚举当前方块的所有落点：先旋转若干次，再平移到目标列，最后软降至落定
+pub fn placements(engine: &Engine) -> Vec<Placement> {
+    let mut result = Vec::new();
+    if engine.topped_out() {
+        return result;
+    }
+    let mut seen = Vec::new();
+    for rotations in 0..4 {
+        for shift in -(COLS as i32)..=COLS as i32 {
+            let step = if shift < 0 { Input::Left } else { Input::Right };
+            let planned = std::iter::repeat_n(Input::Rotate, rotations)
+                .chain(std::iter::repeat_n(step, shift.unsigned_abs() as usize));
+            let mut sim = engine.clone();
+            let mut inputs = Vec::new();
+            let mut lock = None;
+            let mut blocked = false;
+            for input in planned {
+                let before = *sim.piece();
+                inputs.push(input);
+                lock = sim.apply(input);
+                if lock.is_some() {
+                    break;
+                }
+                //转不动或移不动说明这个落点到不了
+                if *sim.piece() == before {
+                    blocked = true;
+                    break;
+                }
+            }
+            if blocked {
+                continue;
+            }
+            if lock.is_none() {
+                //不同的操作序列可能到达同一位置
+                let mut cells = *sim.piece();
+                cells.sort_unstable();
+                if seen.contains(&cells) {
+                    continue;
+                }
+                seen.push(cells);
+            }
+            while lock.is_none() {
+                inputs.push(Input::SoftDrop);
+                lock = sim.apply(Input::SoftDrop);
+            }
+            let lock = lock.unwrap();
+            let mut cells = lock.cells;
+            cells.sort_unstable();
+            if result.iter().any(|p: &Placement| p.cells == cells) {
+                continue;
+            }
+            result.push(Placement {
+                inputs,
+                cells,
+                score: if sim.topped_out() {
+                    f64::NEG_INFINITY
+                } else {
+                    evaluate(sim.board(), lock.lines)
+                },
+            });
+        }
+    }
+    result
+}
+
+pub struct Bot {
+    difficulty: Difficulty,
+    rng: ChaChaRng,
+    //当前方块剩余的操作
+    plan: VecDeque<Input>,
+}
+
+impl Bot {
+    pub fn new(difficulty: Difficulty, seed: u64) -> Self {
+        Bot {
+            difficulty,
+            rng: ChaChaRng::seed_from_u64(seed),
+            plan: VecDeque::new(),
+        }
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    //给出下一条输入，上一个方块的计划用完后为当前方块重新规划
+    pub fn next_input(&mut self, engine: &Engine) -> Option<Input> {
+        if self.plan.is_empty() {
+            if let Some(placement) = self.choose(engine) {
+                self.plan = placement.inputs.into();
+            }
+        }
+        self.plan.pop_front()
+    }
+
+    //方块在计划执行完之前落定(如受重力影响)时丢弃剩余的操作
+    pub fn on_lock(&mut self) {
+        self.plan.clear();
+    }
+
+    fn choose(&mut self, engine: &Engine) -> Option<Placement> {
+        let mut placements = placements(engine);
+        if placements.is_empty() {
+            return None;
+        }
+        if self.rng.gen::<f64>() < self.difficulty.mistake_rate() {
+            let i = self.rng.gen_range(0, placements.len());
+            return Some(placements.swap_remove(i));
+        }
+        placements
+            .into_iter()
+            .max_by(|a, b| a.score.total_cmp(&b.score))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placements_are_distinct_and_replayable() {
+        let engine = Engine::new(5);
+        let placements = placements(&engine);
+        assert!(!placements.is_empty());
+        for (i, placement) in placements.iter().enumerate() {
+            assert!(placements[..i].iter().all(|p| p.cells != placement.cells));
+            //按给出的输入执行恰好在最后一步落定到cells
+            let mut sim = engine.clone();
+            let (last, moves) = placement.inputs.split_last().unwrap();
+            for &input in moves {
+                assert_eq!(sim.apply(input), None);
+            }
+            let mut cells = sim.apply(*last).unwrap().cells;
+            cells.sort_unstable();
+            assert_eq!(cells, placement.cells);
+            assert_eq!(placement.score, evaluate(sim.board(), 0));
+        }
+    }
+
+    #[test]
+    fn every_column_is_reachable() {
+        let placements = placements(&Engine::new(6));
+        for col in 0..COLS as i32 {
+            assert!(placements
+                .iter()
+                .any(|p| p.cells.iter().any(|&(c, _)| c == col)));
+        }
+    }
+
+    #[test]
+    fn holes_and_height_lower_the_score() {
+        let empty = [Cell::Empty; COLS];
+        let mut covered = empty;
+        covered[0] = Cell::Block;
+        let mut floor = empty;
+        floor[0] = Cell::Block;
+        //第0列同样高，有空洞的更差
+        assert!(evaluate(&[floor], 0) > evaluate(&[empty, covered], 0));
+        assert_eq!(evaluate(&[], 0), 0.0);
+        assert!(evaluate(&[], 1) > 0.0);
+    }
+}
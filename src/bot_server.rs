@@ -0,0 +1,107 @@
+//BotService的参考实现：用内置AI的启发式评分在游戏给出的落点中挑选，第三方AI可照此实现
+use russia_block::bot::evaluate;
+use russia_block::engine::{Cell, COLS, ROWS};
+use russia_block::rblock::bot_service_server::{BotService, BotServiceServer};
+use russia_block::rblock::{BotInfo, BotInfoRequest, Piece, SuggestRequest, SuggestResponse};
+use tonic::{transport::Server, Request, Response, Status};
+
+#[derive(Default, Debug)]
+pub struct HeuristicBot {}
+
+#[tonic::async_trait]
+impl BotService for HeuristicBot {
+    async fn info(&self, _request: Request<BotInfoRequest>) -> Result<Response<BotInfo>, Status> {
+        Ok(Response::new(BotInfo {
+            name: "heuristic".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            author: "russia_block".to_string(),
+        }))
+    }
+
+    async fn suggest(
+        &self,
+        request: Request<SuggestRequest>,
+    ) -> Result<Response<SuggestResponse>, Status> {
+        let req = request.into_inner();
+        let board: Vec<[Cell; COLS]> = req
+            .board
+            .unwrap_or_default()
+            .rows
+            .iter()
+            .map(|bits| {
+                let mut cells = [Cell::Empty; COLS];
+                for (col, cell) in cells.iter_mut().enumerate() {
+                    if bits & 1 << col != 0 {
+                        *cell = Cell::Block;
+                    }
+                }
+                cells
+            })
+            .collect();
+        //格子编号为row*COLS+col，超出棋盘的落点无法评分
+        let outside = |option: &Piece| {
+            option
+                .cells
+                .iter()
+                .any(|&cell| cell as usize >= ROWS * COLS)
+        };
+        if req.options.iter().any(outside) {
+            return Err(Status::invalid_argument("placement outside the board"));
+        }
+        let placement = req
+            .options
+            .into_iter()
+            .map(|option| (score(&board, &option), option))
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, option)| option)
+            .ok_or_else(|| Status::invalid_argument("no placement options"))?;
+        Ok(Response::new(SuggestResponse {
+            placement: Some(placement),
+        }))
+    }
+}
+
+//把方块放到落点上并消行，再对局面评分
+fn score(board: &[[Cell; COLS]], option: &Piece) -> f64 {
+    let mut board = board.to_vec();
+    for &cell in option.cells.iter() {
+        let (row, col) = (cell as usize / COLS, cell as usize % COLS);
+        if board.len() <= row {
+            board.resize(row + 1, [Cell::Empty; COLS]);
+        }
+        board[row][col] = Cell::Block;
+    }
+    let before = board.len();
+    board.retain(|cells| cells.contains(&Cell::Empty));
+    evaluate(&board, (before - board.len()) as u32)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = "127.0.0.1:8030".parse().unwrap();
+    Server::builder()
+        .add_service(BotServiceServer::new(HeuristicBot::default()))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn placement_outside_the_board_is_rejected() {
+        let request = SuggestRequest {
+            options: vec![Piece {
+                cells: vec![(ROWS * COLS) as u32],
+            }],
+            ..Default::default()
+        };
+        let status = HeuristicBot::default()
+            .suggest(Request::new(request))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+}
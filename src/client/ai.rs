@@ -0,0 +1,239 @@
+//本地内置AI对手与经BotService接入的外部AI
+use crate::game::{
+    AttackEvent, InputQueue, PauseControl, PieceLockedEvent, RestartEvent, TopOutEvent, COL_NUM,
+};
+use crate::net;
+use crate::versus::{board_snapshot, cell_color, OpponentCell, VersusStatus};
+use bevy::prelude::*;
+use russia_block::bot::{self, Bot, Difficulty};
+use russia_block::engine::{Engine, Input as GameInput};
+use russia_block::rblock::bot_service_client::BotServiceClient;
+use russia_block::rblock::{BotInfoRequest, Piece, SuggestRequest, SuggestResponse};
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::Mutex;
+
+//外部AI：后台任务调用BotService，每个方块请求一次落点
+pub struct ExternalBot {
+    requests: tokio::sync::mpsc::UnboundedSender<SuggestRequest>,
+    responses: Mutex<mpsc::Receiver<SuggestResponse>>,
+    //请求已发出、尚未收到回复
+    waiting: bool,
+    //与外部AI的连接已断开，不再发出请求
+    stopped: bool,
+    //当前方块的操作已排入队列
+    planned: bool,
+    //每次落定或重开加一，回复到达时方块已换则丢弃
+    generation: u32,
+    requested: u32,
+}
+//本地AI对手：独立的引擎与种子，按难度的间隔操作
+pub struct BotOpponent {
+    engine: Engine,
+    bot: Bot,
+    timer: Timer,
+    finished: bool,
+}
+
+impl BotOpponent {
+    pub fn new(difficulty: Difficulty) -> Self {
+        BotOpponent {
+            engine: Engine::new(rand::random()),
+            bot: Bot::new(difficulty, rand::random()),
+            timer: Timer::new(difficulty.interval(), true),
+            finished: false,
+        }
+    }
+}
+
+//AI按间隔执行一条输入；双方的攻击直接送入对方的垃圾行队列，任一方顶出即结束
+#[allow(clippy::too_many_arguments)]
+pub fn bot_system(
+    time: Res<Time>,
+    mut opponent: ResMut<BotOpponent>,
+    mut pause: ResMut<PauseControl>,
+    mut queue: ResMut<InputQueue>,
+    mut attacks: EventReader<AttackEvent>,
+    mut top_out: EventReader<TopOutEvent>,
+    mut restart: EventReader<RestartEvent>,
+    mut status: Query<&mut Text, With<VersusStatus>>,
+) {
+    let mut text = status.single_mut();
+    if restart.iter().next().is_some() {
+        *opponent = BotOpponent::new(opponent.bot.difficulty());
+    }
+    if opponent.finished {
+        return;
+    }
+    for attack in attacks.iter() {
+        opponent.engine.apply(GameInput::Garbage(attack.lines));
+    }
+    if top_out.iter().next().is_some() {
+        opponent.finished = true;
+        text.sections[0].value = format!("You Lose! Bot score:{}", opponent.engine.score());
+        return;
+    }
+    if pause.pause {
+        return;
+    }
+    let BotOpponent {
+        engine, bot, timer, ..
+    } = &mut *opponent;
+    for _ in 0..timer.tick(time.delta()).times_finished() {
+        let input = match bot.next_input(engine) {
+            Some(input) => input,
+            None => break,
+        };
+        let lock = match engine.apply(input) {
+            Some(lock) => lock,
+            None => continue,
+        };
+        bot.on_lock();
+        if lock.attack > 0 {
+            queue.pending.push_back(GameInput::Garbage(lock.attack));
+        }
+        if engine.topped_out() {
+            opponent.finished = true;
+            pause.pause = true;
+            text.sections[0].value = "You Win!".to_string();
+            return;
+        }
+    }
+    text.sections[0].value = format!(
+        "VS Bot ({:?}) score:{}",
+        opponent.bot.difficulty(),
+        opponent.engine.score()
+    );
+}
+
+pub fn bot_board_system(
+    opponent: Res<BotOpponent>,
+    mut cells: Query<(&OpponentCell, &mut Sprite)>,
+) {
+    let board = board_snapshot(&opponent.engine);
+    for (cell, mut sprite) in cells.iter_mut() {
+        sprite.color = cell_color(&board, cell.col, cell.row);
+    }
+}
+
+pub fn connect_external(addr: String) -> ExternalBot {
+    let (requests, mut requests_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (responses_tx, responses) = mpsc::channel();
+    net::spawn(async move {
+        let mut client = match BotServiceClient::connect(addr).await {
+            Ok(client) => client,
+            Err(e) => {
+                error!("bot connect failed: {}", e);
+                return;
+            }
+        };
+        if let Ok(info) = client.info(BotInfoRequest {}).await {
+            let info = info.into_inner();
+            info!(
+                "external bot {} {} by {}",
+                info.name, info.version, info.author
+            );
+        }
+        while let Some(request) = requests_rx.recv().await {
+            match client.suggest(request).await {
+                Ok(response) => {
+                    if responses_tx.send(response.into_inner()).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!("bot suggest failed: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+    ExternalBot {
+        requests,
+        responses: Mutex::new(responses),
+        waiting: false,
+        stopped: false,
+        planned: false,
+        generation: 0,
+        requested: 0,
+    }
+}
+
+//方块所占格子编码为row*COL_NUM+col，按编码排序
+fn piece_of(cells: &[(i32, i32)]) -> Piece {
+    let mut cells: Vec<u32> = cells
+        .iter()
+        .map(|&(col, row)| row as u32 * COL_NUM as u32 + col as u32)
+        .collect();
+    cells.sort_unstable();
+    Piece { cells }
+}
+
+//新方块出场时把局面与可选落点发给外部AI，收到回复后把对应的操作排入队列
+pub fn external_bot_system(
+    pause: Res<PauseControl>,
+    engine: Res<Engine>,
+    mut external: ResMut<ExternalBot>,
+    mut queue: ResMut<InputQueue>,
+    mut locked: EventReader<PieceLockedEvent>,
+    mut restart: EventReader<RestartEvent>,
+) {
+    if locked.iter().count() + restart.iter().count() > 0 {
+        external.generation += 1;
+        external.planned = false;
+    }
+    if external.waiting {
+        let received = external.responses.lock().unwrap().try_recv();
+        let response = match received {
+            Ok(response) => response,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => {
+                error!("external bot disconnected, no more moves will be made");
+                external.waiting = false;
+                external.stopped = true;
+                return;
+            }
+        };
+        external.waiting = false;
+        if external.requested == external.generation {
+            external.planned = true;
+            let mut target: Vec<u32> = response.placement.unwrap_or_default().cells;
+            target.sort_unstable();
+            //回复与请求之间方块可能已受重力下落，按当前位置重新求操作序列
+            match bot::placements(&engine)
+                .into_iter()
+                .find(|placement| piece_of(&placement.cells).cells == target)
+            {
+                Some(placement) => queue.pending.extend(placement.inputs),
+                None => warn!("external bot chose an unreachable placement"),
+            }
+        }
+    }
+    if pause.pause
+        || engine.topped_out()
+        || external.planned
+        || external.waiting
+        || external.stopped
+    {
+        return;
+    }
+    let mut board = board_snapshot(&engine);
+    board.alive.clear();
+    let request = SuggestRequest {
+        board: Some(board),
+        current: Some(piece_of(engine.piece())),
+        queue: vec![piece_of(&engine.next_piece())],
+        options: bot::placements(&engine)
+            .iter()
+            .map(|placement| piece_of(&placement.cells))
+            .collect(),
+        pending_garbage: engine.pending_garbage(),
+        score: engine.score(),
+    };
+    if external.requests.send(request).is_ok() {
+        external.waiting = true;
+        external.requested = external.generation;
+    } else {
+        error!("external bot disconnected, no more moves will be made");
+        external.stopped = true;
+    }
+}
@@ -0,0 +1,482 @@
+//本地对局：棋盘与方块的绘制、输入队列、重力与按键、计分板、顶出与重开
+use crate::leaderboard::{LiveRank, BOARD_SIZE};
+use crate::recorder::Recorder;
+use crate::settings::{self, ClientConfig};
+use bevy::prelude::*;
+use russia_block::engine::{self, Cell, Engine, Input as GameInput};
+use russia_block::finesse::Finesse;
+use std::collections::VecDeque;
+
+#[derive(Component)]
+pub struct Score;
+
+pub struct PauseControl {
+    pub pause: bool,
+}
+
+#[derive(Component)]
+pub struct FinishPicture;
+
+#[derive(Component)]
+pub struct BlockAlive;
+#[derive(Component)]
+pub struct BlockDead;
+#[derive(Component)]
+pub struct BlockNext;
+#[derive(Component)]
+struct BlockWall;
+//当前方块、已落定方块与next区方块
+type AnyBlock = Or<(With<BlockAlive>, With<BlockDead>, With<BlockNext>)>;
+
+//按键、重力与收到的垃圾行都先进入队列，再按顺序交给引擎执行，
+//因此只要种子与输入顺序相同，对局就与帧率无关地完全重现
+#[derive(Default)]
+pub struct InputQueue {
+    pub pending: VecDeque<GameInput>,
+}
+
+//方块落定事件，lines为本次消除的行数
+pub struct PieceLockedEvent {
+    pub lines: usize,
+}
+pub struct TopOutEvent;
+//按回车重新开局
+pub struct RestartEvent;
+//抵消己方垃圾行后发给对手的攻击
+pub struct AttackEvent {
+    pub lines: u32,
+}
+
+const NEXT_CENTER: (f32, f32) = (-425.0, -50.0); //next center(-425.0,-50.0)
+pub const COL_NUM: usize = engine::COLS;
+pub const ROW_NUM: usize = engine::ROWS;
+
+pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, config: Res<ClientConfig>) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    commands.spawn_bundle(UiCameraBundle::default());
+
+    //background
+    for i in 0..COL_NUM {
+        for j in 0..ROW_NUM {
+            commands.spawn_bundle(SpriteBundle {
+                transform: Transform {
+                    translation: Vec3::new(
+                        35.0 * i as f32 - 35.0 * 5.5,
+                        -35.0 * 9.5 + 35.0 * j as f32,
+                        0.0,
+                    ),
+                    scale: Vec3::new(30.0, 30.0, 0.0),
+                    ..Default::default()
+                },
+                sprite: Sprite {
+                    color: settings::color(config.settings.colors.background),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        }
+    }
+    // Add walls
+    let wall_color = Color::rgb(0.0, 0.0, 0.0);
+    let wall_thickness = 5.0;
+    let bounds = Vec2::new(35.0 * 12.0, 35.0 * 20.0);
+
+    // left
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform {
+                translation: Vec3::new(-bounds.x / 2.0, 0.0, 0.0),
+                scale: Vec3::new(wall_thickness, bounds.y + wall_thickness, 1.0),
+                ..Default::default()
+            },
+            sprite: Sprite {
+                color: wall_color,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(BlockWall);
+    // right
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform {
+                translation: Vec3::new(bounds.x / 2.0, 0.0, 0.0),
+                scale: Vec3::new(wall_thickness, bounds.y + wall_thickness, 1.0),
+                ..Default::default()
+            },
+            sprite: Sprite {
+                color: wall_color,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(BlockWall);
+    // bottom
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform {
+                translation: Vec3::new(0.0, -bounds.y / 2.0, 0.0),
+                scale: Vec3::new(bounds.x + wall_thickness, wall_thickness, 1.0),
+                ..Default::default()
+            },
+            sprite: Sprite {
+                color: wall_color,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(BlockWall);
+    // top
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform {
+                translation: Vec3::new(0.0, bounds.y / 2.0, 0.0),
+                scale: Vec3::new(bounds.x + wall_thickness, wall_thickness, 1.0),
+                ..Default::default()
+            },
+            sprite: Sprite {
+                color: wall_color,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(BlockWall);
+
+    // scoreboard
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: "Score: ".to_string(),
+                        style: TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 40.0,
+                            color: Color::rgb(0.5, 0.5, 1.0),
+                        },
+                    },
+                    TextSection {
+                        value: "0".to_string(),
+                        style: TextStyle {
+                            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                            font_size: 40.0,
+                            color: Color::rgb(1.0, 0.5, 0.5),
+                        },
+                    },
+                ],
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(5.0),
+                    left: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Score);
+    //next text
+    commands.spawn_bundle(TextBundle {
+        text: Text {
+            sections: vec![TextSection {
+                value: "Next: ".to_string(),
+                style: TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 40.0,
+                    color: Color::rgb(0.5, 0.5, 1.0),
+                },
+            }],
+            ..Default::default()
+        },
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: Rect {
+                top: Val::Px(255.0), //255
+                left: Val::Px(5.0),  //5
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+}
+
+pub fn pause_system(
+    key_input: ResMut<Input<KeyCode>>,
+    config: Res<ClientConfig>,
+    mut state: ResMut<PauseControl>,
+) {
+    if key_input.pressed(config.settings.keys.pause) {
+        state.pause = !state.pause;
+    }
+}
+
+//游戏中修改的设置写回文件
+pub fn config_save_system(config: Res<ClientConfig>) {
+    if !config.is_changed() || config.is_added() {
+        return;
+    }
+    match config.save() {
+        Ok(()) => info!("settings saved to {}", config.path.display()),
+        Err(e) => error!("settings save failed: {}", e),
+    }
+}
+
+//重力计时，默认每秒下落一格
+pub fn gravity_input_system(pause: Res<PauseControl>, mut queue: ResMut<InputQueue>) {
+    if pause.pause {
+        return;
+    }
+    queue.pending.push_back(GameInput::Gravity);
+}
+
+pub fn keyboard_input_system(
+    key_input: Res<Input<KeyCode>>,
+    config: Res<ClientConfig>,
+    pause: Res<PauseControl>,
+    mut queue: ResMut<InputQueue>,
+) {
+    if pause.pause {
+        return;
+    }
+    let keys = &config.settings.keys;
+    let input = if key_input.pressed(keys.left) {
+        GameInput::Left
+    } else if key_input.pressed(keys.right) {
+        GameInput::Right
+    } else if key_input.pressed(keys.soft_drop) {
+        GameInput::SoftDrop
+    } else if key_input.pressed(keys.rotate) {
+        GameInput::Rotate
+    } else {
+        return;
+    };
+    queue.pending.push_back(input);
+}
+
+//依次把队列中的输入交给引擎执行，并把落定、攻击与顶出转为事件
+#[allow(clippy::too_many_arguments)]
+pub fn apply_input_system(
+    pause: Res<PauseControl>,
+    mut queue: ResMut<InputQueue>,
+    mut recorder: Option<ResMut<Recorder>>,
+    mut finesse: Option<ResMut<Finesse>>,
+    mut engine: ResMut<Engine>,
+    mut locked: EventWriter<PieceLockedEvent>,
+    mut attacks: EventWriter<AttackEvent>,
+    mut top_out: EventWriter<TopOutEvent>,
+) {
+    if pause.pause {
+        return;
+    }
+    while !engine.topped_out() {
+        let input = match queue.pending.pop_front() {
+            Some(input) => input,
+            None => break,
+        };
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record(input);
+        }
+        let lock = engine.apply(input);
+        if let Some(finesse) = finesse.as_mut() {
+            finesse.record(input, lock.as_ref(), &engine);
+        }
+        let lock = match lock {
+            Some(lock) => lock,
+            None => continue,
+        };
+        locked.send(PieceLockedEvent {
+            lines: lock.lines as usize,
+        });
+        if lock.attack > 0 {
+            attacks.send(AttackEvent { lines: lock.attack });
+        }
+        if engine.topped_out() {
+            top_out.send(TopOutEvent);
+        }
+    }
+}
+
+//棋盘格(列,行)在屏幕上的位置
+pub fn cell_translation(col: i32, row: i32) -> Vec3 {
+    Vec3::new(
+        35.0 * col as f32 - 35.0 * 5.5,
+        -35.0 * 9.5 + 35.0 * row as f32,
+        0.0,
+    )
+}
+
+pub fn block_sprite(translation: Vec3, color: Color) -> SpriteBundle {
+    SpriteBundle {
+        transform: Transform {
+            translation,
+            scale: Vec3::new(30.0, 30.0, 0.0),
+            ..Default::default()
+        },
+        sprite: Sprite {
+            color,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+//引擎状态变化后重建当前方块、已落定方块与next区方块
+pub fn render_board_system(
+    mut commands: Commands,
+    engine: Res<Engine>,
+    config: Res<ClientConfig>,
+    blocks: Query<Entity, AnyBlock>,
+) {
+    if !engine.is_changed() {
+        return;
+    }
+    for entity in blocks.iter() {
+        commands.entity(entity).despawn();
+    }
+    let colors = &config.settings.colors;
+    for (row, cells) in engine.board().iter().enumerate().take(ROW_NUM) {
+        for (col, cell) in cells.iter().enumerate() {
+            let color = match cell {
+                Cell::Empty => continue,
+                Cell::Block => settings::color(colors.block),
+                Cell::Garbage => settings::color(colors.garbage),
+            };
+            commands
+                .spawn_bundle(block_sprite(
+                    cell_translation(col as i32, row as i32),
+                    color,
+                ))
+                .insert(BlockDead);
+        }
+    }
+    if !engine.topped_out() {
+        for &(col, row) in engine
+            .piece()
+            .iter()
+            .filter(|(_, row)| *row < ROW_NUM as i32)
+        {
+            commands
+                .spawn_bundle(block_sprite(
+                    cell_translation(col, row),
+                    settings::color(colors.piece),
+                ))
+                .insert(BlockAlive);
+        }
+    }
+    for &(dx, dy) in engine.next_shape().iter() {
+        let translation = Vec3::new(
+            NEXT_CENTER.0 + 17.5 * dx as f32,
+            NEXT_CENTER.1 + 17.5 * dy as f32,
+            0.0,
+        );
+        commands
+            .spawn_bundle(block_sprite(translation, settings::color(colors.next)))
+            .insert(BlockNext);
+    }
+}
+
+//当前分数在榜上前BOARD_SIZE名中的位置，同分同名次；排不进时为None
+pub fn live_rank(scores: &[u32], score: u32) -> Option<usize> {
+    let above = scores.iter().filter(|&&s| s > score).count();
+    match above < BOARD_SIZE as usize {
+        true => Some(above + 1),
+        false => None,
+    }
+}
+
+pub fn scoreboard_system(
+    engine: Res<Engine>,
+    mut rank: ResMut<LiveRank>,
+    mut query: Query<(&Score, &mut Text)>,
+) {
+    let mut updated = engine.is_changed();
+    let LiveRank { link, scores } = &mut *rank;
+    while let Ok(result) = link.results.lock().unwrap().try_recv() {
+        //断线时保留上次的榜
+        if let Ok(board) = result {
+            *scores = Some(board.scores);
+            updated = true;
+        }
+    }
+    if !updated {
+        return;
+    }
+    let (_, mut text) = query.single_mut();
+    let score = engine.score();
+    text.sections[1].value = match scores.as_deref().map(|scores| live_rank(scores, score)) {
+        Some(Some(rank)) => format!("{} rank:{}", score, rank),
+        Some(None) => format!("{} rank:>{}", score, BOARD_SIZE),
+        //还没有连上服务端
+        None => score.to_string(),
+    };
+}
+
+pub fn game_over_system(
+    mut top_out: EventReader<TopOutEvent>,
+    mut pause: ResMut<PauseControl>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    if top_out.iter().next().is_none() {
+        return;
+    }
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![TextSection {
+                    value: "GameOver!".to_string(),
+                    style: TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 80.0,
+                        color: Color::rgb(0.5, 0.5, 1.0),
+                    },
+                }],
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(255.0),
+                    left: Val::Px(555.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(FinishPicture);
+    pause.pause = true;
+}
+
+//回车重新开局：换新种子(设置了固定种子时沿用)，同时开始新的录像
+#[allow(clippy::too_many_arguments)]
+pub fn restart_system(
+    key_input: Res<Input<KeyCode>>,
+    config: Res<ClientConfig>,
+    mut commands: Commands,
+    mut pause: ResMut<PauseControl>,
+    mut engine: ResMut<Engine>,
+    mut recorder: ResMut<Recorder>,
+    mut finesse: ResMut<Finesse>,
+    mut queue: ResMut<InputQueue>,
+    mut restart: EventWriter<RestartEvent>,
+    over_img: Query<Entity, With<FinishPicture>>,
+) {
+    if !key_input.pressed(config.settings.keys.restart) {
+        return;
+    }
+    restart.send(RestartEvent);
+    for entity in over_img.iter() {
+        commands.entity(entity).despawn();
+    }
+    let seed = config.seed.unwrap_or_else(rand::random);
+    *engine = Engine::new(seed);
+    *recorder = Recorder::new(seed, &recorder.mode, recorder.player.clone());
+    *finesse = Finesse::new(&engine);
+    queue.pending.clear();
+    pause.pause = false;
+}
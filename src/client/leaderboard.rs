@@ -0,0 +1,319 @@
+//排行榜面板：按模式与时间段订阅服务端推送的榜
+use crate::game::live_rank;
+use crate::net::{self, server_channel};
+use crate::profile::{NamePrompt, Profile};
+use crate::recorder::Recorder;
+use bevy::prelude::*;
+use russia_block::engine::Engine;
+use russia_block::modes::LEADERBOARD_MODES;
+use russia_block::rblock::score_client::ScoreClient;
+use russia_block::rblock::{LeaderboardUpdate, Period, WatchLeaderboardRequest};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tonic::Response;
+
+//排行榜面板：Tab切换时间段，M切换模式，服务端在榜上有变化时推送当前选中的榜
+struct LeaderboardView {
+    period: usize,
+    mode: usize,
+    board: Option<LeaderboardUpdate>,
+    message: String,
+}
+//后台订阅任务，发送新的请求即改为订阅另一个榜
+pub struct LeaderboardLink {
+    pub requests: tokio::sync::mpsc::UnboundedSender<WatchLeaderboardRequest>,
+    pub results: Mutex<mpsc::Receiver<Result<LeaderboardUpdate, String>>>,
+}
+//计分板上的名次：订阅全部模式总榜的前BOARD_SIZE名，按当前分数在其中的位置计算
+pub struct LiveRank {
+    pub link: LeaderboardLink,
+    pub scores: Option<Vec<u32>>,
+}
+#[derive(Component)]
+struct LeaderboardText;
+
+const BOARD_PERIODS: [(Period, &str); 3] = [
+    (Period::AllTime, "All-time"),
+    (Period::Weekly, "Weekly"),
+    (Period::Daily, "Daily"),
+];
+pub const BOARD_SIZE: u32 = 10;
+//订阅断开后重连的间隔
+const BOARD_RECONNECT: Duration = Duration::from_secs(5);
+
+//本地模式右侧的排行榜面板
+pub fn add_leaderboard(app: &mut App, profile: Arc<Mutex<Profile>>) {
+    let view = LeaderboardView {
+        period: 0,
+        mode: 0,
+        board: None,
+        message: String::new(),
+    };
+    let link = connect_leaderboard(profile);
+    link.requests.send(view.request()).ok();
+    app.insert_resource(view)
+        .insert_resource(link)
+        .add_startup_system(setup_leaderboard)
+        .add_system(leaderboard_key_system)
+        .add_system(leaderboard_text_system);
+}
+
+//第0项为全部模式
+pub fn board_modes() -> impl Iterator<Item = &'static str> {
+    std::iter::once("").chain(LEADERBOARD_MODES)
+}
+
+impl LeaderboardView {
+    fn request(&self) -> WatchLeaderboardRequest {
+        WatchLeaderboardRequest {
+            mode: board_modes().nth(self.mode).unwrap().to_string(),
+            period: BOARD_PERIODS[self.period].0 as i32,
+            topk: BOARD_SIZE,
+            player_id: String::new(),
+        }
+    }
+}
+
+//订阅最近一次选择的榜，收到新的请求时改订；断开后隔一段时间重连。
+//名次按本地保存的player_id推送，还没有注册时在注册后的下一次推送时改订
+pub fn connect_leaderboard(profile: Arc<Mutex<Profile>>) -> LeaderboardLink {
+    let (requests, mut requests_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (results_tx, results) = mpsc::channel();
+    net::spawn(async move {
+        let mut current: WatchLeaderboardRequest = match requests_rx.recv().await {
+            Some(request) => request,
+            None => return,
+        };
+        loop {
+            let mut request = current.clone();
+            request.player_id = profile.lock().unwrap().player_id.clone();
+            let stream = match server_channel() {
+                Ok(channel) => ScoreClient::new(channel)
+                    .watch_leaderboard(request.clone())
+                    .await
+                    .map(Response::into_inner)
+                    .map_err(|status| status.message().to_string()),
+                Err(e) => Err(e),
+            };
+            let error = match stream {
+                Ok(mut stream) => loop {
+                    tokio::select! {
+                        message = stream.message() => match message {
+                            Ok(Some(update)) => {
+                                if results_tx.send(Ok(update)).is_err() {
+                                    return;
+                                }
+                                let registered = request.player_id.is_empty()
+                                    && !profile.lock().unwrap().player_id.is_empty();
+                                if registered {
+                                    break None;
+                                }
+                            }
+                            Ok(None) => break Some("leaderboard closed".to_string()),
+                            Err(status) => break Some(status.message().to_string()),
+                        },
+                        next = requests_rx.recv() => match next {
+                            Some(next) => {
+                                current = next;
+                                break None;
+                            }
+                            None => return,
+                        },
+                    }
+                },
+                Err(e) => Some(e),
+            };
+            let error = match error {
+                Some(error) => error,
+                None => continue,
+            };
+            if results_tx.send(Err(error)).is_err() {
+                return;
+            }
+            //等待重连，期间改选的榜在重连时生效
+            let delay = tokio::time::sleep(BOARD_RECONNECT);
+            tokio::pin!(delay);
+            loop {
+                tokio::select! {
+                    _ = &mut delay => break,
+                    next = requests_rx.recv() => match next {
+                        Some(next) => current = next,
+                        None => return,
+                    },
+                }
+            }
+        }
+    });
+    LeaderboardLink {
+        requests,
+        results: Mutex::new(results),
+    }
+}
+
+fn setup_leaderboard(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![TextSection {
+                    value: String::new(),
+                    style: TextStyle {
+                        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                        font_size: 18.0,
+                        color: Color::rgb(0.5, 0.5, 1.0),
+                    },
+                }],
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(20.0),
+                    left: Val::Px(940.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(LeaderboardText);
+}
+
+//输入名字时不切换
+fn leaderboard_key_system(
+    key_input: Res<Input<KeyCode>>,
+    prompt: Option<Res<NamePrompt>>,
+    link: Res<LeaderboardLink>,
+    mut view: ResMut<LeaderboardView>,
+) {
+    if prompt.is_some() {
+        return;
+    }
+    if key_input.just_pressed(KeyCode::Tab) {
+        view.period = (view.period + 1) % BOARD_PERIODS.len();
+    } else if key_input.just_pressed(KeyCode::M) {
+        view.mode = (view.mode + 1) % board_modes().count();
+    } else {
+        return;
+    }
+    //旧榜在新结果到达前不再显示
+    view.board = None;
+    view.message = "loading...".to_string();
+    link.requests.send(view.request()).ok();
+}
+
+//当前这局计入所选的榜时，标出它此时的位置
+fn leaderboard_text_system(
+    link: Res<LeaderboardLink>,
+    engine: Res<Engine>,
+    recorder: Res<Recorder>,
+    mut view: ResMut<LeaderboardView>,
+    mut text: Query<&mut Text, With<LeaderboardText>>,
+) {
+    let mut updated = view.is_changed() || engine.is_changed();
+    while let Ok(result) = link.results.lock().unwrap().try_recv() {
+        match result {
+            Ok(board) => {
+                view.board = Some(board);
+                view.message.clear();
+            }
+            Err(e) => view.message = e,
+        }
+        updated = true;
+    }
+    if !updated {
+        return;
+    }
+    let mut value = String::from("Leaderboard [Tab] period [M] mode\n");
+    value += &tabs(
+        BOARD_PERIODS.iter().map(|(_, label)| *label).collect(),
+        view.period,
+    );
+    value += "\n";
+    value += &tabs(mode_labels(), view.mode);
+    value += "\n\n";
+    if let Some(board) = &view.board {
+        let mode = board_modes().nth(view.mode).unwrap();
+        let score = engine.score();
+        let playing = score > 0 && (mode.is_empty() || mode == recorder.mode);
+        let mut marker = match playing {
+            true => live_rank(&board.scores, score),
+            false => None,
+        };
+        if board.entries.is_empty() && marker.is_none() {
+            value += "No scores yet\n";
+        }
+        for (i, entry) in board.entries.iter().enumerate() {
+            if marker == Some(i + 1) {
+                value += &format!("{:>3}. {:<16} {:>7}\n", i + 1, "> now", score);
+                marker = None;
+            }
+            value += &format!(
+                "{:>3}. {:<16} {:>7}\n",
+                entry.rank, entry.player, entry.score
+            );
+        }
+        if let Some(rank) = marker {
+            value += &format!("{:>3}. {:<16} {:>7}\n", rank, "> now", score);
+        }
+        if board.rank > 0 {
+            value += &format!("\nYour best #{} {}", board.rank, board.best_score);
+        }
+        if board.resets_at > 0 {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let left = board.resets_at.saturating_sub(now);
+            value += &format!(
+                "\n{} scores, resets in {}h {:02}m",
+                board.total,
+                left / 3600,
+                left % 3600 / 60
+            );
+        } else {
+            value += &format!("\n{} scores", board.total);
+        }
+    }
+    if !view.message.is_empty() {
+        value += &format!("\n{}", view.message);
+    }
+    text.single_mut().sections[0].value = value;
+}
+
+//选项标签，选中的加方括号
+pub fn tabs(labels: Vec<&str>, selected: usize) -> String {
+    labels
+        .iter()
+        .enumerate()
+        .map(|(i, label)| match i == selected {
+            true => format!("[{}]", label),
+            false => format!(" {} ", label),
+        })
+        .collect()
+}
+
+pub fn mode_labels() -> Vec<&'static str> {
+    board_modes()
+        .map(|mode| if mode.is_empty() { "all" } else { mode })
+        .collect()
+}
+
+//unix秒对应的UTC日期
+pub fn format_date(secs: u64) -> String {
+    //按3月1日起算的年份推算公历日期
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{}-{:02}-{:02}", year, month, day)
+}
@@ -0,0 +1,303 @@
+//大厅：房间列表与创建、加入、离开、准备
+use crate::net::{self, server_channel, signed};
+use crate::profile::{optional_session, Profile};
+use crate::royale::{RoyaleLink, RoyaleState};
+use bevy::prelude::*;
+use russia_block::rblock::lobby_client::LobbyClient;
+use russia_block::rblock::royale_message::Payload as RoyaleMessagePayload;
+use russia_block::rblock::{
+    CreateRoomRequest, JoinRoomRequest, JoinRoomResponse, LeaveRoomRequest, ListRoomsRequest,
+    RoomInfo, RoomState, RoyaleJoin, RoyaleMessage, SetReadyRequest,
+};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+enum LobbyCommand {
+    Create {
+        name: String,
+    },
+    Join {
+        room_id: u32,
+    },
+    Leave {
+        room_id: u32,
+        player_id: u32,
+        seat_secret: String,
+    },
+    Ready {
+        room_id: u32,
+        player_id: u32,
+        seat_secret: String,
+        ready: bool,
+    },
+}
+enum LobbyUpdate {
+    Rooms(Vec<RoomInfo>),
+    Room(RoomInfo),
+    Joined(JoinRoomResponse),
+    Left,
+    Error(String),
+}
+//大厅连接：后台任务定时拉取房间列表，并执行创建、加入、离开、准备操作
+pub struct LobbyLink {
+    commands: tokio::sync::mpsc::UnboundedSender<LobbyCommand>,
+    updates: Mutex<mpsc::Receiver<LobbyUpdate>>,
+}
+pub struct Lobby {
+    pub name: String,
+    pub rooms: Vec<RoomInfo>,
+    pub selected: usize,
+    //已加入的(房间号, 玩家号)
+    pub joined: Option<(u32, u32)>,
+    //入座时服务端下发的座位密钥
+    pub seat_secret: String,
+    pub ready: bool,
+    //倒计时开始后已在对局流上接入房间，不能再离开
+    pub attached: bool,
+    pub message: String,
+}
+#[derive(Component)]
+pub struct LobbyText;
+
+//入座按登录的玩家，登录不上时以匿名入座
+pub fn connect_lobby(profile: Arc<Mutex<Profile>>) -> LobbyLink {
+    let (commands, mut commands_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (updates_tx, updates) = mpsc::channel();
+    net::spawn(async move {
+        let token = optional_session(&profile).await;
+        let token = token.as_deref();
+        let mut client = match server_channel() {
+            Ok(channel) => LobbyClient::new(channel),
+            Err(e) => {
+                error!("lobby connect failed: {}", e);
+                return;
+            }
+        };
+        let mut poll = tokio::time::interval(Duration::from_millis(500));
+        loop {
+            let result = tokio::select! {
+                _ = poll.tick() => client
+                    .list_rooms(ListRoomsRequest {})
+                    .await
+                    .map(|r| LobbyUpdate::Rooms(r.into_inner().rooms)),
+                command = commands_rx.recv() => match command {
+                    Some(LobbyCommand::Create { name }) => client
+                        .create_room(signed(
+                            CreateRoomRequest {
+                                room_name: format!("{}'s room", name),
+                                ..Default::default()
+                            },
+                            token,
+                        ))
+                        .await
+                        .map(|r| LobbyUpdate::Joined(r.into_inner())),
+                    Some(LobbyCommand::Join { room_id }) => client
+                        .join_room(signed(
+                            JoinRoomRequest { room_id },
+                            token,
+                        ))
+                        .await
+                        .map(|r| LobbyUpdate::Joined(r.into_inner())),
+                    Some(LobbyCommand::Leave {
+                        room_id,
+                        player_id,
+                        seat_secret,
+                    }) => client
+                        .leave_room(LeaveRoomRequest {
+                            room_id,
+                            player_id,
+                            seat_secret,
+                        })
+                        .await
+                        .map(|_| LobbyUpdate::Left),
+                    Some(LobbyCommand::Ready {
+                        room_id,
+                        player_id,
+                        seat_secret,
+                        ready,
+                    }) => client
+                        .set_ready(SetReadyRequest {
+                            room_id,
+                            player_id,
+                            ready,
+                            seat_secret,
+                        })
+                        .await
+                        .map(|r| LobbyUpdate::Room(r.into_inner())),
+                    None => break,
+                },
+            };
+            let update =
+                result.unwrap_or_else(|status| LobbyUpdate::Error(status.message().to_string()));
+            if updates_tx.send(update).is_err() {
+                break;
+            }
+        }
+    });
+    LobbyLink {
+        commands,
+        updates: Mutex::new(updates),
+    }
+}
+
+//大厅按键：上下选择房间，C创建，J加入，L离开，R准备/取消准备
+pub fn lobby_key_system(
+    key_input: Res<Input<KeyCode>>,
+    link: Res<LobbyLink>,
+    mut lobby: ResMut<Lobby>,
+) {
+    if lobby.attached {
+        return;
+    }
+    let command = match lobby.joined {
+        None => {
+            if key_input.just_pressed(KeyCode::Up) {
+                lobby.selected = lobby.selected.saturating_sub(1);
+            } else if key_input.just_pressed(KeyCode::Down) {
+                lobby.selected = (lobby.selected + 1).min(lobby.rooms.len().saturating_sub(1));
+            }
+            if key_input.just_pressed(KeyCode::C) {
+                Some(LobbyCommand::Create {
+                    name: lobby.name.clone(),
+                })
+            } else if key_input.just_pressed(KeyCode::J) {
+                lobby
+                    .rooms
+                    .get(lobby.selected)
+                    .map(|room| LobbyCommand::Join {
+                        room_id: room.room_id,
+                    })
+            } else {
+                None
+            }
+        }
+        Some((room_id, player_id)) => {
+            if key_input.just_pressed(KeyCode::L) {
+                Some(LobbyCommand::Leave {
+                    room_id,
+                    player_id,
+                    seat_secret: lobby.seat_secret.clone(),
+                })
+            } else if key_input.just_pressed(KeyCode::R) {
+                Some(LobbyCommand::Ready {
+                    room_id,
+                    player_id,
+                    seat_secret: lobby.seat_secret.clone(),
+                    ready: !lobby.ready,
+                })
+            } else {
+                None
+            }
+        }
+    };
+    if let Some(command) = command {
+        let _ = link.commands.send(command);
+    }
+}
+
+pub fn lobby_receive_system(
+    link: Res<LobbyLink>,
+    royale: Res<RoyaleLink>,
+    mut lobby: ResMut<Lobby>,
+) {
+    let updates = link.updates.lock().unwrap();
+    while let Ok(update) = updates.try_recv() {
+        match update {
+            LobbyUpdate::Rooms(rooms) => {
+                lobby.rooms = rooms;
+                lobby.selected = lobby.selected.min(lobby.rooms.len().saturating_sub(1));
+            }
+            LobbyUpdate::Room(room) => {
+                if let Some(old) = lobby.rooms.iter_mut().find(|r| r.room_id == room.room_id) {
+                    *old = room;
+                }
+            }
+            LobbyUpdate::Joined(joined) => {
+                if let Some(room) = joined.room {
+                    lobby.joined = Some((room.room_id, joined.player_id));
+                    lobby.seat_secret = joined.seat_secret;
+                    lobby.message.clear();
+                }
+            }
+            LobbyUpdate::Left => {
+                lobby.joined = None;
+                lobby.seat_secret.clear();
+                lobby.ready = false;
+            }
+            LobbyUpdate::Error(message) => lobby.message = message,
+        }
+    }
+    let (room_id, player_id) = match lobby.joined {
+        Some(joined) => joined,
+        None => return,
+    };
+    let room = match lobby.rooms.iter().find(|r| r.room_id == room_id) {
+        Some(room) => room.clone(),
+        None => return,
+    };
+    lobby.ready = room
+        .players
+        .iter()
+        .any(|p| p.player_id == player_id && p.ready);
+    //倒计时开始后接入对局流，倒计时结束时服务端下发开始消息
+    if !lobby.attached && room.state != RoomState::Waiting as i32 {
+        lobby.attached = true;
+        royale.send(RoyaleMessage {
+            payload: Some(RoyaleMessagePayload::Join(RoyaleJoin {
+                room_id,
+                player_id,
+                seat_secret: lobby.seat_secret.clone(),
+            })),
+        });
+    }
+}
+
+pub fn lobby_text_system(
+    lobby: Res<Lobby>,
+    state: Res<RoyaleState>,
+    mut text: Query<&mut Text, With<LobbyText>>,
+) {
+    let mut text = text.single_mut();
+    if state.started {
+        text.sections[0].value.clear();
+        return;
+    }
+    let mut value = match lobby.joined {
+        None => "Lobby  [C]reate [J]oin\n".to_string(),
+        Some(_) if lobby.attached => "Lobby\n".to_string(),
+        Some(_) => "Lobby  [R]eady [L]eave\n".to_string(),
+    };
+    for (i, room) in lobby.rooms.iter().enumerate() {
+        let joined = lobby.joined.is_some_and(|(id, _)| id == room.room_id);
+        let cursor = if joined || (lobby.joined.is_none() && i == lobby.selected) {
+            ">"
+        } else {
+            " "
+        };
+        let state = match RoomState::from_i32(room.state) {
+            Some(RoomState::Countdown) => {
+                format!("Starting in {}s", room.countdown_ms.div_ceil(1000))
+            }
+            Some(RoomState::Playing) => "Playing".to_string(),
+            _ => "Waiting".to_string(),
+        };
+        value += &format!(
+            "{} {} ({}/{}) {}\n",
+            cursor,
+            room.name,
+            room.players.len(),
+            room.capacity,
+            state
+        );
+        if joined || i == lobby.selected {
+            for player in room.players.iter() {
+                let ready = if player.ready { " [ready]" } else { "" };
+                value += &format!("    {}{}\n", player.name, ready);
+            }
+        }
+    }
+    if !lobby.message.is_empty() {
+        value += &format!("\n{}", lobby.message);
+    }
+    text.sections[0].value = value;
+}
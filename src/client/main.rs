@@ -0,0 +1,431 @@
+use ai::{bot_board_system, bot_system, connect_external, external_bot_system, BotOpponent};
+use bevy::{core::FixedTimestep, prelude::*};
+use game::{
+    apply_input_system, config_save_system, game_over_system, gravity_input_system,
+    keyboard_input_system, pause_system, render_board_system, restart_system, scoreboard_system,
+    setup, AttackEvent, InputQueue, PauseControl, PieceLockedEvent, RestartEvent, TopOutEvent,
+};
+use leaderboard::{add_leaderboard, connect_leaderboard, LiveRank, BOARD_SIZE};
+use lobby::{connect_lobby, lobby_key_system, lobby_receive_system, lobby_text_system, Lobby};
+use profile::{
+    name_prompt_system, setup_name_prompt, start_session, NamePrompt, PlayerIdentity, Profile,
+};
+use prost::Message;
+use recorder::{replay_save_system, Recorder, AI_MODE};
+use replay::{
+    replay_control_system, replay_driver_system, replay_reset_system, replay_text_system,
+    setup_replay, ReplayPlayer,
+};
+use royale::{
+    connect_royale, parse_targeting, royale_board_system, royale_receive_system,
+    royale_send_system, royale_targeting_system, setup_royale, RoyaleState,
+};
+use russia_block::bot::Difficulty;
+use russia_block::engine::Engine;
+use russia_block::finesse::Finesse;
+use russia_block::rblock;
+use settings::ClientConfig;
+use spectate::{
+    connect_spectator, setup_spectate, spectate_key_system, spectate_receive_system,
+    spectate_text_system, Spectating,
+};
+use stats::{
+    connect_stats, setup_stats, stats_graph_system, stats_key_system, stats_text_system, StatsView,
+};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use training::{
+    finesse_text_system, setup_finesse, setup_training, training_system, TrainingTarget,
+};
+use versus::{
+    add_garbage_systems, add_spectator_list, connect_versus, setup_versus, versus_board_system,
+    versus_receive_system, versus_send_system, VersusState,
+};
+
+use rblock::{Period, Replay, WatchLeaderboardRequest};
+
+mod ai;
+mod game;
+mod leaderboard;
+mod lobby;
+mod net;
+mod profile;
+mod recorder;
+mod replay;
+mod royale;
+mod settings;
+mod spectate;
+mod stats;
+mod training;
+mod versus;
+
+enum Mode {
+    Single,
+    Versus(String),
+    Royale(String),
+    //观众名与观战延迟(毫秒，0为服务端默认)
+    Spectate(String, u32),
+    Replay(Replay),
+    Bot(Difficulty),
+    //外部AI的BotService地址
+    External(String),
+    //按键精简度练习，高亮每个方块的目标落点
+    Training,
+    //查看玩家的历史成绩与个人最佳
+    Profile(String),
+}
+
+fn main() {
+    //client versus [name] 进入1v1对战，client royale [name] 进入多人混战
+    //client spectate [name] [delay_secs] 观战进行中的对局，client replay <file> 回放录像
+    //client bot [easy|normal|hard] 在本地与内置AI对战，client external [addr] 由外部AI代替键盘操作
+    //client training 练习按键精简度，client profile [player_id] 查看历史成绩，默认为自己
+    //--mode <mode> 与第一个参数等价，--server <url> 指定服务端，--seed <n> 固定种子，--config <file> 指定设置文件
+    //未在命令行给出名字时使用本地保存的名字
+    let cli = match settings::parse_args(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let mut config = match ClientConfig::load(cli.config.unwrap_or_else(settings::default_path)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("invalid settings {}", e);
+            return;
+        }
+    };
+    config.seed = cli.seed.or(config.seed);
+    if let Err(e) = settings::init_connection(&config.settings, cli.server) {
+        eprintln!("{}", e);
+        return;
+    }
+    let profile = Profile::load();
+    let default_name = profile
+        .as_ref()
+        .map_or_else(|| "player".to_string(), |p| p.name.clone());
+    let mut args = cli.rest.into_iter();
+    let mode_name = match cli.mode {
+        Some(mode) => mode,
+        None => args.next().unwrap_or_else(|| config.settings.mode.clone()),
+    };
+    let mode = match mode_name.as_str() {
+        "versus" => Mode::Versus(args.next().unwrap_or_else(|| default_name.clone())),
+        "royale" => Mode::Royale(args.next().unwrap_or_else(|| default_name.clone())),
+        "spectate" => Mode::Spectate(
+            args.next().unwrap_or_else(|| "spectator".to_string()),
+            args.next()
+                .and_then(|secs| secs.parse::<u32>().ok())
+                .map_or(0, |secs| secs * 1000),
+        ),
+        "replay" => {
+            let path = args.next().unwrap_or_default();
+            match std::fs::read(&path).map(|bytes| Replay::decode(&*bytes)) {
+                Ok(Ok(replay)) => Mode::Replay(replay),
+                Ok(Err(e)) => {
+                    eprintln!("invalid replay {}: {}", path, e);
+                    return;
+                }
+                Err(e) => {
+                    eprintln!("cannot read replay {}: {}", path, e);
+                    return;
+                }
+            }
+        }
+        "bot" => match args.next() {
+            None => Mode::Bot(Difficulty::Normal),
+            Some(name) => match Difficulty::parse(&name) {
+                Some(difficulty) => Mode::Bot(difficulty),
+                None => {
+                    eprintln!("unknown difficulty {}", name);
+                    return;
+                }
+            },
+        },
+        "external" => Mode::External(
+            args.next()
+                .unwrap_or_else(|| config.settings.bot_addr.clone()),
+        ),
+        "training" => Mode::Training,
+        "profile" => match args
+            .next()
+            .or_else(|| profile.as_ref().map(|p| p.player_id.clone()))
+        {
+            Some(player_id) if !player_id.is_empty() => Mode::Profile(player_id),
+            _ => {
+                eprintln!("not registered yet, give a player_id");
+                return;
+            }
+        },
+        "single" => Mode::Single,
+        other => {
+            eprintln!("unknown mode {}", other);
+            return;
+        }
+    };
+
+    //观战模式只渲染服务端推送的棋盘，不运行本地游戏
+    if let Mode::Spectate(name, delay_ms) = mode {
+        App::new()
+            .add_plugins(DefaultPlugins)
+            .insert_resource(connect_spectator())
+            .insert_resource(Spectating {
+                name,
+                delay_ms,
+                games: Vec::new(),
+                selected: 0,
+                watching: None,
+                delay: Duration::ZERO,
+                spectators: Vec::new(),
+                message: String::new(),
+            })
+            .add_startup_system(setup_spectate)
+            .add_system(spectate_key_system)
+            .add_system(spectate_receive_system)
+            .add_system(spectate_text_system)
+            .add_system(bevy::input::system::exit_on_esc_system)
+            .run();
+        return;
+    }
+
+    if let Mode::Profile(player_id) = mode {
+        let link = connect_stats();
+        let view = StatsView {
+            player_id,
+            mode: 0,
+            metric: 0,
+            stats: None,
+            message: "loading...".to_string(),
+        };
+        link.requests.send(view.request()).ok();
+        App::new()
+            .add_plugins(DefaultPlugins)
+            .insert_resource(link)
+            .insert_resource(view)
+            .add_startup_system(setup_stats)
+            .add_system(stats_key_system)
+            .add_system(stats_text_system)
+            .add_system(stats_graph_system)
+            .add_system(bevy::input::system::exit_on_esc_system)
+            .run();
+        return;
+    }
+
+    let seed = match &mode {
+        Mode::Replay(replay) => replay.seed,
+        _ => config.seed.unwrap_or_else(rand::random),
+    };
+
+    let engine = Engine::new(seed);
+    let settings = config.settings.clone();
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins)
+        .insert_resource(config)
+        .insert_resource(engine.clone())
+        .insert_resource(InputQueue::default())
+        //对战模式下等待配对成功后才开始
+        .insert_resource(PauseControl {
+            pause: matches!(mode, Mode::Versus(_) | Mode::Royale(_)),
+        })
+        .add_event::<PieceLockedEvent>()
+        .add_event::<AttackEvent>()
+        .add_event::<TopOutEvent>()
+        .add_startup_system(setup)
+        .add_system(apply_input_system.label("apply_input"))
+        .add_system(render_board_system.after("apply_input"))
+        .add_system(game_over_system.after("apply_input"))
+        .add_system(config_save_system)
+        .add_system(bevy::input::system::exit_on_esc_system);
+    let (mode_name, player) = match &mode {
+        Mode::Versus(name) => ("versus", name.clone()),
+        Mode::Royale(name) => ("royale", name.clone()),
+        Mode::Bot(_) => ("bot", default_name),
+        Mode::External(_) => (AI_MODE, "bot".to_string()),
+        Mode::Training => ("training", default_name),
+        _ => ("single", default_name),
+    };
+    //对战模式首次启动时按命令行给出的名字注册
+    let named = match (&profile, &mode) {
+        (None, Mode::Versus(name) | Mode::Royale(name)) => Some(Profile {
+            name: name.clone(),
+            ..Default::default()
+        }),
+        _ => None,
+    };
+    let shared_profile = Arc::new(Mutex::new(profile.clone().or(named).unwrap_or_default()));
+    if !matches!(mode, Mode::Replay(_)) {
+        let identity = PlayerIdentity {
+            profile: shared_profile.clone(),
+        };
+        add_live_systems(
+            &mut app,
+            Recorder::new(seed, mode_name, player),
+            Finesse::new(&engine),
+            settings.timing.gravity_secs,
+            identity.profile.clone(),
+            !matches!(mode, Mode::Versus(_) | Mode::Royale(_)),
+        );
+        if matches!(mode, Mode::Single | Mode::Training) {
+            add_leaderboard(&mut app, identity.profile.clone());
+        }
+        match &profile {
+            //本地模式首次启动时先输入名字
+            None if matches!(mode, Mode::Single | Mode::Bot(_) | Mode::Training) => {
+                app.insert_resource(NamePrompt::default())
+                    .add_startup_system(setup_name_prompt)
+                    .add_system(name_prompt_system);
+            }
+            //登录，上次注册没有成功则重新注册
+            Some(_) => start_session(identity.profile.clone()),
+            None => {}
+        }
+        app.insert_resource(identity);
+    }
+    //外部AI模式下由AI代替键盘操作
+    if !matches!(mode, Mode::Replay(_) | Mode::External(_)) {
+        app.add_system_set(
+            SystemSet::new()
+                .with_run_criteria(FixedTimestep::step(settings.timing.key_repeat_secs))
+                .with_system(keyboard_input_system),
+        );
+    }
+    match mode {
+        Mode::Single | Mode::Spectate(..) | Mode::Profile(_) => {}
+        Mode::Training => {
+            app.insert_resource(TrainingTarget::default())
+                .add_startup_system(setup_training)
+                .add_system(training_system.after("apply_input"));
+        }
+        Mode::Replay(replay) => {
+            let player = match ReplayPlayer::new(replay, settings.replay_speed) {
+                Ok(player) => player,
+                Err(e) => {
+                    eprintln!("invalid replay: {}", e);
+                    return;
+                }
+            };
+            add_garbage_systems(&mut app);
+            app.insert_resource(player)
+                .add_startup_system(setup_replay)
+                .add_system(replay_control_system.label("replay_control"))
+                .add_system(
+                    replay_reset_system
+                        .label("replay_reset")
+                        .after("replay_control"),
+                )
+                .add_system(
+                    replay_driver_system
+                        .after("replay_reset")
+                        .before("apply_input"),
+                )
+                .add_system(replay_text_system);
+        }
+        Mode::External(addr) => {
+            app.insert_resource(connect_external(addr))
+                .add_system(external_bot_system.before("apply_input"));
+        }
+        Mode::Bot(difficulty) => {
+            add_garbage_systems(&mut app);
+            app.insert_resource(BotOpponent::new(difficulty))
+                .add_startup_system(setup_versus)
+                .add_system(bot_system.after("apply_input"))
+                .add_system_set(
+                    SystemSet::new()
+                        .with_run_criteria(FixedTimestep::step(1.0 / 10.0))
+                        .with_system(bot_board_system),
+                );
+        }
+        Mode::Versus(_) => {
+            add_garbage_systems(&mut app);
+            add_spectator_list(&mut app);
+            app.insert_resource(connect_versus(shared_profile))
+                .insert_resource(VersusState::default())
+                .add_startup_system(setup_versus)
+                .add_system_set(
+                    SystemSet::new()
+                        .with_run_criteria(FixedTimestep::step(1.0 / 10.0))
+                        .with_system(versus_board_system),
+                )
+                .add_system(versus_send_system)
+                .add_system(versus_receive_system);
+        }
+        Mode::Royale(name) => {
+            add_garbage_systems(&mut app);
+            add_spectator_list(&mut app);
+            app.insert_resource(connect_royale(shared_profile.clone()))
+                .insert_resource(connect_lobby(shared_profile))
+                .insert_resource(Lobby {
+                    name,
+                    rooms: Vec::new(),
+                    selected: 0,
+                    joined: None,
+                    seat_secret: String::new(),
+                    ready: false,
+                    attached: false,
+                    message: String::new(),
+                })
+                .insert_resource(RoyaleState {
+                    room_id: 0,
+                    started: false,
+                    finished: false,
+                    targeting: parse_targeting(&settings.royale_targeting),
+                })
+                .add_startup_system(setup_royale)
+                .add_system_set(
+                    SystemSet::new()
+                        .with_run_criteria(FixedTimestep::step(1.0 / 10.0))
+                        .with_system(royale_board_system),
+                )
+                .add_system(royale_send_system)
+                .add_system(royale_targeting_system)
+                .add_system(royale_receive_system)
+                .add_system(lobby_key_system)
+                .add_system(lobby_receive_system)
+                .add_system(lobby_text_system);
+        }
+    }
+    app.run();
+}
+
+//本地游玩：键盘与重力计时产生输入，录像并向服务端查询排名
+fn add_live_systems(
+    app: &mut App,
+    recorder: Recorder,
+    finesse: Finesse,
+    gravity_secs: f64,
+    profile: Arc<Mutex<Profile>>,
+    local: bool,
+) {
+    let link = connect_leaderboard(profile);
+    link.requests
+        .send(WatchLeaderboardRequest {
+            mode: String::new(),
+            period: Period::AllTime as i32,
+            topk: BOARD_SIZE,
+            player_id: String::new(),
+        })
+        .ok();
+    app.insert_resource(recorder)
+        .insert_resource(finesse)
+        .insert_resource(LiveRank { link, scores: None })
+        .add_event::<RestartEvent>()
+        .add_system_set(
+            SystemSet::new()
+                .with_run_criteria(FixedTimestep::step(gravity_secs))
+                .with_system(gravity_input_system),
+        )
+        .add_system(scoreboard_system)
+        .add_system(replay_save_system)
+        .add_startup_system(setup_finesse)
+        .add_system(finesse_text_system.after("apply_input"));
+    //联网对局中暂停或重开会与对手不同步，只在本地对局中启用
+    if local {
+        app.add_system_set(
+            SystemSet::new()
+                .with_run_criteria(FixedTimestep::step(1.0 / 10.0))
+                .with_system(pause_system)
+                .with_system(restart_system.before("apply_input")),
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//网络：所有请求在同一个后台tokio运行时中执行，并共用一条到服务端的gRPC连接
+use crate::settings::{self, Connection};
+use bevy::prelude::*;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex, OnceLock};
+use tokio::runtime::Runtime;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+use tonic::{Request, Streaming};
+
+//网络任务所在的运行时，首次使用时启动
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+//各服务共用的连接，首次请求时才真正建立，断开后自动重连
+static CHANNEL: OnceLock<Result<Channel, String>> = OnceLock::new();
+const NETWORK_THREADS: usize = 2;
+
+fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(NETWORK_THREADS)
+            .thread_name("network")
+            .enable_all()
+            .build()
+            .unwrap()
+    })
+}
+
+//在后台运行时中执行网络任务，结果经通道交给bevy系统
+pub fn spawn<F>(task: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    runtime().spawn(task);
+}
+
+//到服务端的共用连接；地址或证书有误时每次都返回同一个错误
+pub fn server_channel() -> Result<Channel, String> {
+    CHANNEL
+        .get_or_init(|| {
+            //连接的后台任务需在运行时中创建
+            let _guard = runtime().enter();
+            open_channel()
+        })
+        .clone()
+}
+
+//https地址按设置中的CA校验服务端证书，未给出CA时使用系统根证书
+fn open_channel() -> Result<Channel, String> {
+    let Connection { server, tls } = settings::connection();
+    let mut endpoint = Endpoint::from_shared(server.clone()).map_err(|e| e.to_string())?;
+    if server.starts_with("https://") {
+        let read =
+            |path: &PathBuf| std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e));
+        let mut config = ClientTlsConfig::new();
+        if let Some(ca) = &tls.ca {
+            config = config.ca_certificate(Certificate::from_pem(read(ca)?));
+        }
+        match (&tls.cert, &tls.key) {
+            (Some(cert), Some(key)) => {
+                config = config.identity(Identity::from_pem(read(cert)?, read(key)?))
+            }
+            (None, None) => {}
+            _ => return Err("client certificate and key must be set together".to_string()),
+        }
+        endpoint = endpoint.tls_config(config).map_err(|e| e.to_string())?;
+    }
+    Ok(endpoint.connect_lazy())
+}
+
+//服务端双向流连接：发送通道交给后台任务，接收通道由bevy系统轮询
+pub struct StreamLink<M, E> {
+    outgoing: tokio::sync::mpsc::UnboundedSender<M>,
+    pub incoming: Mutex<mpsc::Receiver<E>>,
+}
+
+//会话令牌放在authorization元数据中
+pub fn authorize<T>(request: &mut Request<T>, token: &str) {
+    request.metadata_mut().insert(
+        "authorization",
+        format!("Bearer {}", token).parse().unwrap(),
+    );
+}
+
+//有令牌时带上令牌，否则为匿名请求
+pub fn signed<T>(message: T, token: Option<&str>) -> Request<T> {
+    let mut request = Request::new(message);
+    if let Some(token) = token {
+        authorize(&mut request, token);
+    }
+    request
+}
+
+impl<M: Send + 'static, E: Send + 'static> StreamLink<M, E> {
+    //后台任务维持双向流，避免阻塞渲染
+    pub fn connect<F, Fut>(open: F) -> Self
+    where
+        F: FnOnce(UnboundedReceiverStream<M>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Streaming<E>, Box<dyn std::error::Error>>> + Send,
+    {
+        let (outgoing, outgoing_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (incoming_tx, incoming) = mpsc::channel();
+        spawn(async move {
+            let mut inbound = match open(UnboundedReceiverStream::new(outgoing_rx)).await {
+                Ok(inbound) => inbound,
+                Err(e) => {
+                    error!("stream connect failed: {}", e);
+                    return;
+                }
+            };
+            while let Ok(Some(event)) = inbound.message().await {
+                if incoming_tx.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        StreamLink {
+            outgoing,
+            incoming: Mutex::new(incoming),
+        }
+    }
+
+    pub fn send(&self, message: M) {
+        let _ = self.outgoing.send(message);
+    }
+}
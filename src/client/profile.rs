@@ -0,0 +1,237 @@
+//本地身份文件的读写、登录与注册，以及首次启动时输入名字的界面
+use crate::game::PauseControl;
+use crate::net::{self, server_channel};
+use crate::recorder::Recorder;
+use crate::settings;
+use bevy::prelude::*;
+use russia_block::rblock::players_client::PlayersClient;
+use russia_block::rblock::{LoginRequest, RegisterRequest};
+use russia_block::NAME_MAX;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tonic::Status;
+
+//玩家身份：首次启动时输入名字，注册成功后记下服务端分配的player_id
+#[derive(Clone, Default)]
+pub struct Profile {
+    pub player_id: String,
+    pub name: String,
+    pub secret: String,
+    //本次运行登录得到的会话令牌，不保存
+    pub token: String,
+}
+//后台注册与成绩提交共用
+pub struct PlayerIdentity {
+    pub profile: Arc<Mutex<Profile>>,
+}
+//首次启动的输入名字界面，确认前游戏暂停
+#[derive(Default)]
+pub struct NamePrompt {
+    name: String,
+}
+#[derive(Component)]
+pub struct NamePromptText;
+
+//旧版本放在当前目录的玩家身份文件，读到后移到配置目录
+const LEGACY_PROFILE_PATH: &str = "profile.txt";
+
+impl Profile {
+    pub fn load() -> Option<Profile> {
+        if let Some(profile) = Profile::read(&settings::profile_path()) {
+            return Some(profile);
+        }
+        let profile = Profile::read(Path::new(LEGACY_PROFILE_PATH))?;
+        match profile.save() {
+            Ok(()) => {
+                let _ = std::fs::remove_file(LEGACY_PROFILE_PATH);
+            }
+            Err(e) => error!("profile save failed: {}", e),
+        }
+        Some(profile)
+    }
+
+    //依次为player_id、名字与登录密钥
+    fn read(path: &Path) -> Option<Profile> {
+        let text = std::fs::read_to_string(path).ok()?;
+        let mut lines = text.lines().map(str::trim);
+        let mut player_id = lines.next()?.to_string();
+        let name = lines.next()?.to_string();
+        let secret = lines.next().unwrap_or_default().to_string();
+        if name.is_empty() {
+            return None;
+        }
+        //没有登录密钥的身份无法登录，只能重新注册
+        if secret.is_empty() {
+            player_id.clear();
+        }
+        Some(Profile {
+            player_id,
+            name,
+            secret,
+            token: String::new(),
+        })
+    }
+
+    //文件中有登录密钥，只允许本人读写
+    fn save(&self) -> std::io::Result<()> {
+        let path = settings::profile_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&path)?;
+        //mode只对新建的文件生效，旧文件另行收紧权限
+        #[cfg(unix)]
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+        write!(file, "{}\n{}\n{}\n", self.player_id, self.name, self.secret)
+    }
+}
+
+//有名字时登录，没有名字或登录不上时返回None，以匿名继续
+pub async fn optional_session(profile: &Arc<Mutex<Profile>>) -> Option<String> {
+    if profile.lock().unwrap().name.is_empty() {
+        return None;
+    }
+    match ensure_session(profile).await {
+        Ok(token) => Some(token),
+        Err(e) => {
+            warn!("login failed, continuing anonymously: {}", e);
+            None
+        }
+    }
+}
+
+//还没有会话时登录，没有player_id则先注册并保存服务端分配的编号与密钥；返回会话令牌
+pub async fn ensure_session(profile: &Arc<Mutex<Profile>>) -> Result<String, Status> {
+    let Profile {
+        player_id,
+        name,
+        secret,
+        token,
+    } = profile.lock().unwrap().clone();
+    if !token.is_empty() {
+        return Ok(token);
+    }
+    let mut client = PlayersClient::new(server_channel().map_err(Status::unavailable)?);
+    let session = if player_id.is_empty() {
+        client
+            .register(RegisterRequest {
+                player_id: String::new(),
+                name,
+            })
+            .await?
+    } else {
+        client.login(LoginRequest { player_id, secret }).await?
+    }
+    .into_inner();
+    let mut profile = profile.lock().unwrap();
+    if let Some(registered) = session.profile {
+        profile.player_id = registered.player_id;
+        profile.name = registered.name;
+    }
+    if !session.secret.is_empty() {
+        profile.secret = session.secret;
+        match profile.save() {
+            Ok(()) => info!("registered as {} ({})", profile.name, profile.player_id),
+            Err(e) => error!("profile save failed: {}", e),
+        }
+    }
+    profile.token = session.token.clone();
+    Ok(session.token)
+}
+
+//后台登录(或注册)，失败时提交成绩前再试
+pub fn start_session(profile: Arc<Mutex<Profile>>) {
+    net::spawn(async move {
+        if let Err(e) = ensure_session(&profile).await {
+            warn!("login failed: {}", e);
+        }
+    });
+}
+
+pub fn setup_name_prompt(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: 40.0,
+        color: Color::rgb(0.5, 0.5, 1.0),
+    };
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: "Enter your name: ".to_string(),
+                        style: style.clone(),
+                    },
+                    TextSection {
+                        value: "_".to_string(),
+                        style: TextStyle {
+                            color: Color::rgb(1.0, 0.5, 0.5),
+                            ..style
+                        },
+                    },
+                ],
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(255.0),
+                    left: Val::Px(455.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(NamePromptText);
+}
+
+//输入名字，回车确认后保存到本地并注册
+#[allow(clippy::too_many_arguments)]
+pub fn name_prompt_system(
+    mut commands: Commands,
+    prompt: Option<ResMut<NamePrompt>>,
+    mut chars: EventReader<ReceivedCharacter>,
+    key_input: Res<Input<KeyCode>>,
+    mut pause: ResMut<PauseControl>,
+    identity: Res<PlayerIdentity>,
+    mut recorder: ResMut<Recorder>,
+    mut text: Query<(Entity, &mut Text), With<NamePromptText>>,
+) {
+    let mut prompt = match prompt {
+        Some(prompt) => prompt,
+        None => return,
+    };
+    pause.pause = true;
+    for c in chars.iter() {
+        if !c.char.is_control() && prompt.name.chars().count() < NAME_MAX {
+            prompt.name.push(c.char);
+        }
+    }
+    if key_input.just_pressed(KeyCode::Back) {
+        prompt.name.pop();
+    }
+    let (entity, mut text) = text.single_mut();
+    let name = prompt.name.trim().to_string();
+    if key_input.just_pressed(KeyCode::Return) && !name.is_empty() {
+        {
+            let mut profile = identity.profile.lock().unwrap();
+            profile.name = name.clone();
+            if let Err(e) = profile.save() {
+                error!("profile save failed: {}", e);
+            }
+        }
+        start_session(identity.profile.clone());
+        recorder.player = name;
+        commands.remove_resource::<NamePrompt>();
+        commands.entity(entity).despawn();
+        pause.pause = false;
+        return;
+    }
+    text.sections[1].value = format!("{}_", prompt.name);
+}
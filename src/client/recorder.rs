@@ -0,0 +1,149 @@
+//本局的录像与成绩上传
+use crate::game::TopOutEvent;
+use crate::net::{self, server_channel, signed};
+use crate::profile::{optional_session, PlayerIdentity, Profile};
+use bevy::prelude::*;
+use prost::Message;
+use russia_block::engine::{self, Engine, Input as GameInput};
+use russia_block::rblock::score_client::ScoreClient;
+use russia_block::rblock::{InputEvent, Replay, SubmitScoreRequest};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tonic::{Code, Response, Status};
+
+//录像：记录种子与每条执行过的输入，顶出后写入REPLAY_DIR
+pub struct Recorder {
+    //提交成绩时的去重编号，每局一个
+    game_id: String,
+    seed: u64,
+    pub mode: String,
+    pub player: String,
+    recorded_at: u64,
+    //首条输入执行的时刻，对战模式下等待配对的时间不计入
+    started: Option<Instant>,
+    last_ms: u32,
+    events: Vec<InputEvent>,
+    saved: bool,
+}
+
+const REPLAY_DIR: &str = "replays";
+//外部AI对局的模式名
+pub const AI_MODE: &str = "external";
+
+//成绩提交失败时的重试次数与间隔
+const SUBMIT_ATTEMPTS: u32 = 3;
+const SUBMIT_RETRY: Duration = Duration::from_secs(2);
+
+impl Recorder {
+    pub fn new(seed: u64, mode: &str, player: String) -> Self {
+        Recorder {
+            game_id: format!("{:016x}", rand::random::<u64>()),
+            seed,
+            mode: mode.to_string(),
+            player,
+            recorded_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            started: None,
+            last_ms: 0,
+            events: Vec::new(),
+            saved: false,
+        }
+    }
+
+    pub fn record(&mut self, input: GameInput) {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        let ms = started.elapsed().as_millis() as u32;
+        self.events.push(input.to_event(ms - self.last_ms));
+        self.last_ms = ms;
+    }
+
+    fn replay(&self, score: u32) -> Replay {
+        Replay {
+            version: engine::REPLAY_VERSION,
+            seed: self.seed,
+            mode: self.mode.clone(),
+            player: self.player.clone(),
+            recorded_at: self.recorded_at,
+            duration_ms: self.last_ms,
+            score,
+            events: self.events.clone(),
+        }
+    }
+
+    fn save(&self, replay: &Replay) -> std::io::Result<std::path::PathBuf> {
+        std::fs::create_dir_all(REPLAY_DIR)?;
+        let path = std::path::Path::new(REPLAY_DIR).join(format!(
+            "{}-{}-{:016x}.replay",
+            self.recorded_at, self.mode, self.seed
+        ));
+        std::fs::write(&path, replay.encode_to_vec())?;
+        Ok(path)
+    }
+}
+
+//顶出后保存录像，并连同分数上传，由服务端重放核对后计入排行
+pub fn replay_save_system(
+    engine: Res<Engine>,
+    identity: Res<PlayerIdentity>,
+    mut recorder: ResMut<Recorder>,
+    mut top_out: EventReader<TopOutEvent>,
+) {
+    if top_out.iter().next().is_none() || recorder.saved {
+        return;
+    }
+    recorder.saved = true;
+    let replay = recorder.replay(engine.score());
+    match recorder.save(&replay) {
+        Ok(path) => info!("replay saved to {}", path.display()),
+        Err(e) => error!("replay save failed: {}", e),
+    }
+    //外部AI代打的对局不是玩家的成绩，只保存录像
+    if recorder.mode == AI_MODE {
+        return;
+    }
+    submit_score(recorder.game_id.clone(), identity.profile.clone(), replay);
+}
+
+//服务端按game_id去重，连接失败时可以放心重试；登录不上时匿名提交
+fn submit_score(game_id: String, profile: Arc<Mutex<Profile>>, replay: Replay) {
+    net::spawn(async move {
+        let score = replay.score;
+        for attempt in 1..=SUBMIT_ATTEMPTS {
+            let token = optional_session(&profile).await;
+            let request = signed(
+                SubmitScoreRequest {
+                    game_id: game_id.clone(),
+                    replay: Some(replay.clone()),
+                    player_id: String::new(),
+                },
+                token.as_deref(),
+            );
+            let response = match server_channel() {
+                Ok(channel) => ScoreClient::new(channel).submit_score(request).await,
+                Err(e) => Err(Status::unavailable(e)),
+            };
+            match response.map(Response::into_inner) {
+                Ok(response) if response.accepted => {
+                    info!("score {} accepted, rank {}", score, response.rank);
+                    return;
+                }
+                Ok(response) => {
+                    warn!("score {} rejected by server: {}", score, response.reason);
+                    return;
+                }
+                Err(e) => {
+                    //令牌过期，下次重试前重新登录
+                    if e.code() == Code::Unauthenticated {
+                        profile.lock().unwrap().token.clear();
+                    }
+                    error!(
+                        "score submit failed ({}/{}): {}",
+                        attempt, SUBMIT_ATTEMPTS, e
+                    )
+                }
+            }
+            tokio::time::sleep(SUBMIT_RETRY).await;
+        }
+    });
+}
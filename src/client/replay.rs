@@ -0,0 +1,219 @@
+//回放界面：回放控制、输入驱动与进度显示
+use crate::game::{FinishPicture, InputQueue, PauseControl};
+use crate::settings::ClientConfig;
+use bevy::prelude::*;
+use russia_block::engine::{Engine, Input as GameInput};
+use russia_block::rblock::{InputEvent, InputKind, Replay};
+use std::collections::VecDeque;
+
+//录像回放：按录像时间把输入送入队列，支持暂停、变速、逐条步进与跳转
+pub struct ReplayPlayer {
+    replay: Replay,
+    //每条输入相对录像开始的时刻(毫秒)
+    times: Vec<u32>,
+    //下一条待送出的输入
+    cursor: usize,
+    clock: f64,
+    speed: usize,
+    paused: bool,
+    //向后跳转需要从头重放
+    restart: bool,
+    //最近送出的输入，供屏幕显示
+    recent: VecDeque<InputEvent>,
+}
+#[derive(Component)]
+pub struct ReplayText;
+
+const REPLAY_SPEEDS: [f64; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+const REPLAY_SEEK_MS: f64 = 5000.0;
+const REPLAY_RECENT: usize = 8;
+
+fn replay_speed_index(speed: f64) -> usize {
+    (0..REPLAY_SPEEDS.len())
+        .min_by(|&a, &b| {
+            (REPLAY_SPEEDS[a] - speed)
+                .abs()
+                .total_cmp(&(REPLAY_SPEEDS[b] - speed).abs())
+        })
+        .unwrap()
+}
+
+impl ReplayPlayer {
+    //speed为设置中的倍速，取最接近的一档；总时长超出u32毫秒的录像无法播放
+    pub fn new(replay: Replay, speed: f64) -> Result<Self, String> {
+        let mut time: u32 = 0;
+        let mut times = Vec::with_capacity(replay.events.len());
+        for event in replay.events.iter() {
+            time = time
+                .checked_add(event.delta_ms)
+                .ok_or("replay is too long")?;
+            times.push(time);
+        }
+        Ok(ReplayPlayer {
+            replay,
+            times,
+            cursor: 0,
+            clock: 0.0,
+            speed: replay_speed_index(speed),
+            paused: false,
+            restart: false,
+            recent: VecDeque::new(),
+        })
+    }
+
+    fn duration(&self) -> f64 {
+        self.times.last().copied().unwrap_or(0) as f64
+    }
+
+    fn seek(&mut self, target: f64) {
+        let target = target.clamp(0.0, self.duration());
+        if target < self.clock {
+            self.restart = true;
+        }
+        self.clock = target;
+    }
+}
+
+pub fn setup_replay(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![TextSection {
+                    value: String::new(),
+                    style: TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 24.0,
+                        color: Color::rgb(0.5, 0.5, 1.0),
+                    },
+                }],
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(100.0),
+                    left: Val::Px(940.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(ReplayText);
+}
+
+//空格暂停，上下调速，句点步进一条输入，左右跳转5秒，Home回到开头
+pub fn replay_control_system(
+    key_input: Res<Input<KeyCode>>,
+    mut config: ResMut<ClientConfig>,
+    mut player: ResMut<ReplayPlayer>,
+) {
+    if key_input.just_pressed(KeyCode::Space) {
+        player.paused = !player.paused;
+    }
+    if key_input.just_pressed(KeyCode::Up) {
+        player.speed = (player.speed + 1).min(REPLAY_SPEEDS.len() - 1);
+        config.settings.replay_speed = REPLAY_SPEEDS[player.speed];
+    } else if key_input.just_pressed(KeyCode::Down) {
+        player.speed = player.speed.saturating_sub(1);
+        config.settings.replay_speed = REPLAY_SPEEDS[player.speed];
+    }
+    if key_input.just_pressed(KeyCode::Period) {
+        player.paused = true;
+        if let Some(&time) = player.times.get(player.cursor) {
+            player.clock = time as f64;
+        }
+    }
+    if key_input.just_pressed(KeyCode::Left) {
+        let target = player.clock - REPLAY_SEEK_MS;
+        player.seek(target);
+    } else if key_input.just_pressed(KeyCode::Right) {
+        let target = player.clock + REPLAY_SEEK_MS;
+        player.seek(target);
+    } else if key_input.just_pressed(KeyCode::Home) {
+        player.seek(0.0);
+    }
+}
+
+//从头重放：用录像的种子重新开局
+pub fn replay_reset_system(
+    mut commands: Commands,
+    mut player: ResMut<ReplayPlayer>,
+    mut engine: ResMut<Engine>,
+    mut pause: ResMut<PauseControl>,
+    mut queue: ResMut<InputQueue>,
+    over_img: Query<Entity, With<FinishPicture>>,
+) {
+    if !player.restart {
+        return;
+    }
+    player.restart = false;
+    player.cursor = 0;
+    player.recent.clear();
+    for entity in over_img.iter() {
+        commands.entity(entity).despawn();
+    }
+    *engine = Engine::new(player.replay.seed);
+    pause.pause = false;
+    queue.pending.clear();
+}
+
+pub fn replay_driver_system(
+    time: Res<Time>,
+    mut player: ResMut<ReplayPlayer>,
+    mut queue: ResMut<InputQueue>,
+) {
+    if !player.paused {
+        let clock = player.clock + time.delta_seconds_f64() * 1000.0 * REPLAY_SPEEDS[player.speed];
+        player.clock = clock.min(player.duration());
+    }
+    while player.cursor < player.times.len() && player.times[player.cursor] as f64 <= player.clock {
+        let event = player.replay.events[player.cursor].clone();
+        let input = GameInput::from_event(&event);
+        if let Some(input) = input {
+            queue.pending.push_back(input);
+        }
+        if input != Some(GameInput::Gravity) {
+            player.recent.push_front(event);
+            player.recent.truncate(REPLAY_RECENT);
+        }
+        player.cursor += 1;
+    }
+}
+
+fn input_label(event: &InputEvent) -> String {
+    match InputKind::from_i32(event.kind) {
+        Some(InputKind::Left) => "Left".to_string(),
+        Some(InputKind::Right) => "Right".to_string(),
+        Some(InputKind::SoftDrop) => "Down".to_string(),
+        Some(InputKind::Rotate) => "Rotate".to_string(),
+        Some(InputKind::Gravity) => "Gravity".to_string(),
+        Some(InputKind::Garbage) => format!("Garbage +{}", event.lines),
+        None => "?".to_string(),
+    }
+}
+
+pub fn replay_text_system(
+    player: Res<ReplayPlayer>,
+    engine: Res<Engine>,
+    mut text: Query<&mut Text, With<ReplayText>>,
+) {
+    let replay = &player.replay;
+    let mut value = format!(
+        "Replay: {} ({})\nScore: {} / {}\n{:.1}s / {:.1}s  x{}{}\n\n",
+        replay.player,
+        replay.mode,
+        engine.score(),
+        replay.score,
+        player.clock / 1000.0,
+        player.duration() / 1000.0,
+        REPLAY_SPEEDS[player.speed],
+        if player.paused { "  [Paused]" } else { "" }
+    );
+    for (i, event) in player.recent.iter().enumerate() {
+        let cursor = if i == 0 { ">" } else { " " };
+        value += &format!("{} {}\n", cursor, input_label(event));
+    }
+    value += "\n[Space] pause [Up/Down] speed\n[.] step [Left/Right] seek [Home] start";
+    text.single_mut().sections[0].value = value;
+}
@@ -0,0 +1,267 @@
+//多人混战：对局流、攻击目标切换与排名
+use crate::game::{AttackEvent, InputQueue, PauseControl, TopOutEvent};
+use crate::lobby::LobbyText;
+use crate::net::{server_channel, signed, StreamLink};
+use crate::profile::{optional_session, Profile};
+use crate::settings::ClientConfig;
+use crate::versus::board_snapshot;
+use bevy::prelude::*;
+use russia_block::engine::{Engine, Input as GameInput};
+use russia_block::rblock::royale_event::Payload as RoyalePayload;
+use russia_block::rblock::royale_message::Payload as RoyaleMessagePayload;
+use russia_block::rblock::score_client::ScoreClient;
+use russia_block::rblock::{Attack, RoyaleEvent, RoyaleMessage, Spectators, Targeting, TopOut};
+use std::sync::{Arc, Mutex};
+
+pub type RoyaleLink = StreamLink<RoyaleMessage, RoyaleEvent>;
+
+pub struct RoyaleState {
+    pub room_id: u32,
+    pub started: bool,
+    pub finished: bool,
+    pub targeting: Targeting,
+}
+#[derive(Component)]
+pub struct RoyaleStatus;
+#[derive(Component)]
+pub struct RoyaleStandings;
+
+//对局流先建立，待大厅中的房间开始倒计时后再发送加入消息；登录不上时以匿名加入
+pub fn connect_royale(profile: Arc<Mutex<Profile>>) -> RoyaleLink {
+    StreamLink::connect(|outgoing| async move {
+        let token = optional_session(&profile).await;
+        let mut client = ScoreClient::new(server_channel()?);
+        Ok(client
+            .royale(signed(outgoing, token.as_deref()))
+            .await?
+            .into_inner())
+    })
+}
+
+pub fn setup_royale(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: String::new(),
+                        style: TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 30.0,
+                            color: Color::rgb(0.5, 0.5, 1.0),
+                        },
+                    },
+                    TextSection {
+                        value: String::new(),
+                        style: TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 24.0,
+                            color: Color::rgb(1.0, 0.5, 0.5),
+                        },
+                    },
+                ],
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(100.0),
+                    left: Val::Px(940.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(RoyaleStatus);
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![TextSection {
+                    value: String::new(),
+                    style: TextStyle {
+                        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                        font_size: 20.0,
+                        color: Color::rgb(0.5, 0.5, 1.0),
+                    },
+                }],
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(180.0),
+                    left: Val::Px(940.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(RoyaleStandings);
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![TextSection {
+                    value: String::new(),
+                    style: TextStyle {
+                        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                        font_size: 20.0,
+                        color: Color::rgb(0.5, 0.5, 1.0),
+                    },
+                }],
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(100.0),
+                    left: Val::Px(940.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(LobbyText);
+}
+
+pub fn royale_board_system(link: Res<RoyaleLink>, state: Res<RoyaleState>, engine: Res<Engine>) {
+    if !state.started || state.finished {
+        return;
+    }
+    let board = board_snapshot(&engine);
+    link.send(RoyaleMessage {
+        payload: Some(RoyaleMessagePayload::Board(board)),
+    });
+}
+
+pub fn royale_send_system(
+    link: Res<RoyaleLink>,
+    mut state: ResMut<RoyaleState>,
+    mut attacks: EventReader<AttackEvent>,
+    mut top_out: EventReader<TopOutEvent>,
+) {
+    if !state.started || state.finished {
+        return;
+    }
+    for attack in attacks.iter() {
+        link.send(RoyaleMessage {
+            payload: Some(RoyaleMessagePayload::Attack(Attack {
+                lines: attack.lines,
+            })),
+        });
+    }
+    if top_out.iter().next().is_some() {
+        state.finished = true;
+        link.send(RoyaleMessage {
+            payload: Some(RoyaleMessagePayload::TopOut(TopOut {})),
+        });
+    }
+}
+
+//设置中的攻击目标名，无法识别时随机
+pub fn parse_targeting(name: &str) -> Targeting {
+    match name {
+        "attackers" => Targeting::Attackers,
+        "kos" => Targeting::Kos,
+        "badges" => Targeting::Badges,
+        _ => Targeting::Random,
+    }
+}
+
+//数字键1-4切换攻击目标策略
+pub fn royale_targeting_system(
+    key_input: Res<Input<KeyCode>>,
+    link: Res<RoyaleLink>,
+    mut config: ResMut<ClientConfig>,
+    mut state: ResMut<RoyaleState>,
+    mut status: Query<&mut Text, With<RoyaleStatus>>,
+) {
+    if !state.started || state.finished {
+        return;
+    }
+    let targeting = if key_input.just_pressed(KeyCode::Key1) {
+        Targeting::Random
+    } else if key_input.just_pressed(KeyCode::Key2) {
+        Targeting::Attackers
+    } else if key_input.just_pressed(KeyCode::Key3) {
+        Targeting::Kos
+    } else if key_input.just_pressed(KeyCode::Key4) {
+        Targeting::Badges
+    } else {
+        return;
+    };
+    state.targeting = targeting;
+    config.settings.royale_targeting = format!("{:?}", targeting).to_lowercase();
+    status.single_mut().sections[1].value = format!("\nTarget: {:?} (1-4)", targeting);
+    link.send(RoyaleMessage {
+        payload: Some(RoyaleMessagePayload::Targeting(targeting as i32)),
+    });
+}
+
+pub fn royale_receive_system(
+    link: Res<RoyaleLink>,
+    mut state: ResMut<RoyaleState>,
+    mut pause: ResMut<PauseControl>,
+    mut queue: ResMut<InputQueue>,
+    mut spectators: ResMut<Spectators>,
+    mut status: Query<&mut Text, (With<RoyaleStatus>, Without<RoyaleStandings>)>,
+    mut standings: Query<&mut Text, (With<RoyaleStandings>, Without<RoyaleStatus>)>,
+) {
+    let incoming = link.incoming.lock().unwrap();
+    let mut status = status.single_mut();
+    while let Ok(RoyaleEvent {
+        payload: Some(payload),
+    }) = incoming.try_recv()
+    {
+        match payload {
+            RoyalePayload::Joined(joined) => {
+                state.room_id = joined.room_id;
+            }
+            RoyalePayload::Start(_) => {
+                state.started = true;
+                //服务端默认随机，设置中保存了其他目标时开局即发送
+                if state.targeting != Targeting::Random {
+                    link.send(RoyaleMessage {
+                        payload: Some(RoyaleMessagePayload::Targeting(state.targeting as i32)),
+                    });
+                }
+                pause.pause = false;
+                status.sections[0].value = format!("Room {}", state.room_id);
+                status.sections[1].value = format!("\nTarget: {:?} (1-4)", state.targeting);
+            }
+            RoyalePayload::Garbage(attack) => {
+                if attack.lines > 0 {
+                    queue.pending.push_back(GameInput::Garbage(attack.lines));
+                }
+            }
+            RoyalePayload::Standings(list) if state.started => {
+                let mut text = format!("Alive: {}/{}\n", list.alive, list.players.len());
+                for player in list.players.iter() {
+                    let place = if player.alive {
+                        "  ".to_string()
+                    } else {
+                        format!("{:>2}", player.placement)
+                    };
+                    text += &format!(
+                        "{} {:<10} KO:{} B:{}\n",
+                        place, player.name, player.kos, player.badges
+                    );
+                }
+                standings.single_mut().sections[0].value = text;
+            }
+            RoyalePayload::Standings(_) => {}
+            RoyalePayload::GameOver(over) => {
+                state.finished = true;
+                pause.pause = true;
+                status.sections[0].value = if over.win {
+                    "You Win!".to_string()
+                } else {
+                    format!("Placement: #{}", over.placement)
+                };
+            }
+            RoyalePayload::Spectators(list) => *spectators = list,
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//客户端设置：保存在系统配置目录下的russia_block/client.toml，启动时读入为ClientConfig资源，
+//游戏中修改的设置写回文件。服务端地址与证书可再由环境变量和命令行覆盖，覆盖值不写回
+use bevy::prelude::{Color, KeyCode};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const CONFIG_FILE: &str = "client.toml";
+const PROFILE_FILE: &str = "profile.txt";
+const CONFIG_DIR: &str = "russia_block";
+const SERVER_ENV: &str = "RBLOCK_SERVER";
+const CA_ENV: &str = "RBLOCK_CA";
+const CLIENT_CERT_ENV: &str = "RBLOCK_CLIENT_CERT";
+const CLIENT_KEY_ENV: &str = "RBLOCK_CLIENT_KEY";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    //服务端地址，https开头时启用TLS
+    pub server: String,
+    pub bot_addr: String,
+    //命令行未指定模式时进入的模式
+    pub mode: String,
+    //固定种子，每局都用同一方块序列
+    pub seed: Option<u64>,
+    //royale的攻击目标：random、attackers、kos或badges
+    pub royale_targeting: String,
+    //录像回放倍速
+    pub replay_speed: f64,
+    pub tls: TlsSettings,
+    pub keys: KeyBindings,
+    pub timing: Timing,
+    pub colors: Colors,
+}
+
+//未给出CA时使用系统根证书，给出客户端证书与私钥时用于mTLS
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsSettings {
+    pub ca: Option<PathBuf>,
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub soft_drop: KeyCode,
+    pub rotate: KeyCode,
+    pub pause: KeyCode,
+    pub restart: KeyCode,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timing {
+    //重力下落间隔(秒)
+    pub gravity_secs: f64,
+    //按住方向键时的重复间隔(秒)
+    pub key_repeat_secs: f64,
+}
+
+//RGB，各分量0到1
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Colors {
+    pub background: [f32; 3],
+    pub block: [f32; 3],
+    pub garbage: [f32; 3],
+    pub piece: [f32; 3],
+    pub next: [f32; 3],
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            server: "http://127.0.0.1:8020".to_string(),
+            bot_addr: "http://127.0.0.1:8030".to_string(),
+            mode: "single".to_string(),
+            seed: None,
+            royale_targeting: "random".to_string(),
+            replay_speed: 1.0,
+            tls: TlsSettings::default(),
+            keys: KeyBindings::default(),
+            timing: Timing::default(),
+            colors: Colors::default(),
+        }
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            left: KeyCode::Left,
+            right: KeyCode::Right,
+            soft_drop: KeyCode::Down,
+            rotate: KeyCode::Up,
+            pause: KeyCode::Space,
+            restart: KeyCode::Return,
+        }
+    }
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing {
+            gravity_secs: 1.0,
+            key_repeat_secs: 1.0 / 16.0,
+        }
+    }
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors {
+            background: [0.8, 0.8, 0.8],
+            block: [0.5, 0.7, 0.2],
+            garbage: [0.4, 0.4, 0.4],
+            piece: [0.5, 0.5, 0.5],
+            next: [0.2, 0.3, 0.7],
+        }
+    }
+}
+
+pub fn color(rgb: [f32; 3]) -> Color {
+    Color::rgb(rgb[0], rgb[1], rgb[2])
+}
+
+pub struct ClientConfig {
+    pub settings: Settings,
+    //写回的位置
+    pub path: PathBuf,
+    //本次运行使用的种子，命令行可覆盖设置中的种子
+    pub seed: Option<u64>,
+}
+
+impl ClientConfig {
+    //文件不存在时使用默认设置；格式错误时报错而不是退回默认值，避免写回时覆盖用户的文件
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        let settings = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Settings::default(),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+        let timing = &settings.timing;
+        if !(timing.gravity_secs > 0.0 && timing.key_repeat_secs > 0.0) {
+            return Err(format!("{}: timing must be positive", path.display()));
+        }
+        Ok(ClientConfig {
+            seed: settings.seed,
+            settings,
+            path,
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let text = toml::to_string(&self.settings).map_err(|e| e.to_string())?;
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        std::fs::write(&self.path, text).map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+}
+
+//系统配置目录下的设置文件，取不到配置目录时放在当前目录
+pub fn default_path() -> PathBuf {
+    match dirs::config_dir() {
+        Some(dir) => dir.join(CONFIG_DIR).join(CONFIG_FILE),
+        None => Path::new(CONFIG_FILE).to_path_buf(),
+    }
+}
+
+//玩家身份与默认设置文件放在同一目录
+pub fn profile_path() -> PathBuf {
+    default_path().with_file_name(PROFILE_FILE)
+}
+
+//命令行：--config <file> --server <url> --seed <n> --mode <mode>，其余为模式参数；
+//不认识的--选项报错，不当作模式参数
+pub struct Args {
+    pub config: Option<PathBuf>,
+    pub server: Option<String>,
+    pub seed: Option<u64>,
+    pub mode: Option<String>,
+    pub rest: Vec<String>,
+}
+
+pub fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        config: None,
+        server: None,
+        seed: None,
+        mode: None,
+        rest: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--config" => parsed.config = Some(value()?.into()),
+            "--server" => parsed.server = Some(value()?),
+            "--seed" => {
+                let seed = value()?;
+                parsed.seed = Some(seed.parse().map_err(|_| format!("invalid seed {}", seed))?);
+            }
+            "--mode" => parsed.mode = Some(value()?),
+            option if option.starts_with("--") => return Err(format!("unknown option {}", option)),
+            _ => parsed.rest.push(arg),
+        }
+    }
+    Ok(parsed)
+}
+
+//连接服务端所需的地址与证书，启动时确定，网络任务中读取
+#[derive(Clone, Debug)]
+pub struct Connection {
+    pub server: String,
+    pub tls: TlsSettings,
+}
+
+static CONNECTION: OnceLock<Connection> = OnceLock::new();
+
+//设置 < 环境变量 < 命令行；只能初始化一次
+pub fn init_connection(settings: &Settings, server: Option<String>) -> Result<(), String> {
+    let env = |name: &str| std::env::var(name).ok();
+    let tls = &settings.tls;
+    let connection = Connection {
+        server: server
+            .or_else(|| env(SERVER_ENV))
+            .unwrap_or_else(|| settings.server.clone()),
+        tls: TlsSettings {
+            ca: env(CA_ENV).map(PathBuf::from).or_else(|| tls.ca.clone()),
+            cert: env(CLIENT_CERT_ENV)
+                .map(PathBuf::from)
+                .or_else(|| tls.cert.clone()),
+            key: env(CLIENT_KEY_ENV)
+                .map(PathBuf::from)
+                .or_else(|| tls.key.clone()),
+        },
+    };
+    CONNECTION
+        .set(connection)
+        .map_err(|_| "connection already initialized".to_string())
+}
+
+pub fn connection() -> &'static Connection {
+    CONNECTION.get().expect("connection not initialized")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn unknown_option_is_rejected() {
+        let e = parse(&["--sever", "http://localhost:8020"]).err().unwrap();
+        assert!(e.contains("--sever"), "{}", e);
+    }
+
+    #[test]
+    fn options_and_rest() {
+        let args = parse(&["--seed", "42", "bot", "--mode", "sprint", "hard"]).unwrap();
+        assert_eq!(args.seed, Some(42));
+        assert_eq!(args.mode.as_deref(), Some("sprint"));
+        assert_eq!(args.rest, ["bot", "hard"]);
+        assert!(args.server.is_none() && args.config.is_none());
+        let args = parse(&["--config", "a.toml", "--server", "https://x:1"]).unwrap();
+        assert_eq!(args.config, Some(PathBuf::from("a.toml")));
+        assert_eq!(args.server.as_deref(), Some("https://x:1"));
+    }
+
+    #[test]
+    fn missing_or_bad_values_are_rejected() {
+        let e = parse(&["--seed"]).err().unwrap();
+        assert!(e.contains("needs a value"), "{}", e);
+        let e = parse(&["--seed", "-1"]).err().unwrap();
+        assert!(e.contains("invalid seed"), "{}", e);
+    }
+}
@@ -0,0 +1,342 @@
+//观战界面：选择进行中的对局，按服务端延迟推送的事件绘制各玩家的棋盘
+use crate::game::{COL_NUM, ROW_NUM};
+use crate::net::{self, server_channel};
+use crate::versus::cell_color;
+use bevy::prelude::*;
+use russia_block::rblock::spectate_event::Payload as SpectatePayload;
+use russia_block::rblock::spectator_client::SpectatorClient;
+use russia_block::rblock::{
+    GameKind, GameSummary, ListGamesRequest, SpectateEvent, SpectateRequest,
+};
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+enum SpectateUpdate {
+    Games(Vec<GameSummary>),
+    Event(SpectateEvent),
+    Error(String),
+}
+//观战连接：后台任务定时拉取可观战的对局，收到订阅请求后转发该对局的事件
+pub struct SpectateLink {
+    commands: tokio::sync::mpsc::UnboundedSender<SpectateRequest>,
+    updates: Mutex<mpsc::Receiver<SpectateUpdate>>,
+}
+pub struct Spectating {
+    pub name: String,
+    pub delay_ms: u32,
+    pub games: Vec<GameSummary>,
+    pub selected: usize,
+    pub watching: Option<GameSummary>,
+    pub delay: Duration,
+    pub spectators: Vec<String>,
+    pub message: String,
+}
+#[derive(Component)]
+pub struct SpectateCell {
+    player: usize,
+    col: usize,
+    row: usize,
+}
+#[derive(Component)]
+pub struct SpectateLabel {
+    player: usize,
+}
+#[derive(Component)]
+pub struct SpectateText;
+
+pub fn connect_spectator() -> SpectateLink {
+    let (commands, mut commands_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (updates_tx, updates) = mpsc::channel();
+    net::spawn(async move {
+        let mut client = match server_channel() {
+            Ok(channel) => SpectatorClient::new(channel),
+            Err(e) => {
+                error!("spectator connect failed: {}", e);
+                return;
+            }
+        };
+        let mut poll = tokio::time::interval(Duration::from_millis(500));
+        //当前订阅的对局，切换对局时中止旧的订阅
+        let mut watching: Option<tokio::task::JoinHandle<()>> = None;
+        loop {
+            tokio::select! {
+                _ = poll.tick() => {
+                    let update = client
+                        .list_games(ListGamesRequest {})
+                        .await
+                        .map(|r| SpectateUpdate::Games(r.into_inner().games))
+                        .unwrap_or_else(|status| {
+                            SpectateUpdate::Error(status.message().to_string())
+                        });
+                    if updates_tx.send(update).is_err() {
+                        break;
+                    }
+                }
+                request = commands_rx.recv() => {
+                    let request = match request {
+                        Some(request) => request,
+                        None => break,
+                    };
+                    if let Some(task) = watching.take() {
+                        task.abort();
+                    }
+                    let mut client = client.clone();
+                    let updates_tx = updates_tx.clone();
+                    watching = Some(tokio::spawn(async move {
+                        let mut events = match client.spectate(request).await {
+                            Ok(response) => response.into_inner(),
+                            Err(status) => {
+                                let message = status.message().to_string();
+                                let _ = updates_tx.send(SpectateUpdate::Error(message));
+                                return;
+                            }
+                        };
+                        while let Ok(Some(event)) = events.message().await {
+                            if updates_tx.send(SpectateUpdate::Event(event)).is_err() {
+                                break;
+                            }
+                        }
+                    }));
+                }
+            }
+        }
+    });
+    SpectateLink {
+        commands,
+        updates: Mutex::new(updates),
+    }
+}
+
+pub fn setup_spectate(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    commands.spawn_bundle(UiCameraBundle::default());
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![TextSection {
+                    value: String::new(),
+                    style: TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 20.0,
+                        color: Color::rgb(0.5, 0.5, 1.0),
+                    },
+                }],
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(20.0),
+                    left: Val::Px(940.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(SpectateText);
+}
+
+//观战棋盘布局：返回格子边长与每块棋盘左下角的位置，两人对战放大显示，多人时每行四块
+fn spectate_layout(players: usize) -> (f32, Vec<(f32, f32)>) {
+    if players <= 2 {
+        let origins = (0..players)
+            .map(|i| (-600.0 + 400.0 * i as f32, -300.0))
+            .collect();
+        (28.0, origins)
+    } else {
+        let origins = (0..players)
+            .map(|i| {
+                let y = if i < 4 { 20.0 } else { -320.0 };
+                (-620.0 + 220.0 * (i % 4) as f32, y)
+            })
+            .collect();
+        (14.0, origins)
+    }
+}
+
+//上下选择对局，回车开始观战
+pub fn spectate_key_system(
+    key_input: Res<Input<KeyCode>>,
+    link: Res<SpectateLink>,
+    mut spectating: ResMut<Spectating>,
+) {
+    if key_input.just_pressed(KeyCode::Up) {
+        spectating.selected = spectating.selected.saturating_sub(1);
+    } else if key_input.just_pressed(KeyCode::Down) {
+        spectating.selected =
+            (spectating.selected + 1).min(spectating.games.len().saturating_sub(1));
+    }
+    if key_input.just_pressed(KeyCode::Return) {
+        if let Some(game) = spectating.games.get(spectating.selected) {
+            let _ = link.commands.send(SpectateRequest {
+                kind: game.kind,
+                game_id: game.game_id,
+                name: spectating.name.clone(),
+                delay_ms: spectating.delay_ms,
+            });
+        }
+    }
+}
+
+pub fn spectate_receive_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    link: Res<SpectateLink>,
+    mut spectating: ResMut<Spectating>,
+    mut cells: Query<(Entity, &SpectateCell, &mut Sprite)>,
+    mut labels: Query<(Entity, &SpectateLabel, &mut Text)>,
+) {
+    let updates = link.updates.lock().unwrap();
+    while let Ok(update) = updates.try_recv() {
+        let payload = match update {
+            SpectateUpdate::Games(games) => {
+                spectating.games = games;
+                spectating.selected = spectating
+                    .selected
+                    .min(spectating.games.len().saturating_sub(1));
+                continue;
+            }
+            SpectateUpdate::Error(message) => {
+                spectating.message = message;
+                continue;
+            }
+            SpectateUpdate::Event(SpectateEvent {
+                payload: Some(payload),
+            }) => payload,
+            SpectateUpdate::Event(_) => continue,
+        };
+        match payload {
+            SpectatePayload::Start(start) => {
+                for (entity, _, _) in cells.iter() {
+                    commands.entity(entity).despawn();
+                }
+                for (entity, _, _) in labels.iter() {
+                    commands.entity(entity).despawn();
+                }
+                let game = start.game.unwrap_or_default();
+                let (size, origins) = spectate_layout(game.players.len());
+                for (player, (info, &(x, y))) in game.players.iter().zip(origins.iter()).enumerate()
+                {
+                    for col in 0..COL_NUM {
+                        for row in 0..ROW_NUM {
+                            commands
+                                .spawn_bundle(SpriteBundle {
+                                    transform: Transform {
+                                        translation: Vec3::new(
+                                            x + size * (col as f32 + 0.5),
+                                            y + size * (row as f32 + 0.5),
+                                            0.0,
+                                        ),
+                                        scale: Vec3::new(size - 2.0, size - 2.0, 0.0),
+                                        ..Default::default()
+                                    },
+                                    sprite: Sprite {
+                                        color: Color::rgb(0.8, 0.8, 0.8),
+                                        ..Default::default()
+                                    },
+                                    ..Default::default()
+                                })
+                                .insert(SpectateCell { player, col, row });
+                        }
+                    }
+                    commands
+                        .spawn_bundle(Text2dBundle {
+                            text: Text::with_section(
+                                info.name.clone(),
+                                TextStyle {
+                                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                    font_size: 20.0,
+                                    color: Color::rgb(0.5, 0.5, 1.0),
+                                },
+                                TextAlignment {
+                                    vertical: VerticalAlign::Center,
+                                    horizontal: HorizontalAlign::Center,
+                                },
+                            ),
+                            transform: Transform::from_xyz(
+                                x + size * COL_NUM as f32 / 2.0,
+                                y + size * ROW_NUM as f32 + 15.0,
+                                0.0,
+                            ),
+                            ..Default::default()
+                        })
+                        .insert(SpectateLabel { player });
+                }
+                spectating.delay = Duration::from_millis(start.delay_ms as u64);
+                spectating.watching = Some(game);
+                spectating.spectators.clear();
+                spectating.message.clear();
+            }
+            SpectatePayload::Board(update) => {
+                let player = spectating.watching.as_ref().and_then(|game| {
+                    game.players
+                        .iter()
+                        .position(|p| p.player_id == update.player_id)
+                });
+                let (player, board) = match (player, update.board) {
+                    (Some(player), Some(board)) => (player, board),
+                    _ => continue,
+                };
+                for (_, cell, mut sprite) in cells.iter_mut() {
+                    if cell.player == player {
+                        sprite.color = cell_color(&board, cell.col, cell.row);
+                    }
+                }
+            }
+            SpectatePayload::Out(out) => {
+                let player = spectating.watching.as_ref().and_then(|game| {
+                    game.players
+                        .iter()
+                        .position(|p| p.player_id == out.player_id)
+                });
+                for (_, label, mut text) in labels.iter_mut() {
+                    if Some(label.player) == player {
+                        text.sections[0].value += &format!(" #{}", out.placement);
+                    }
+                }
+            }
+            SpectatePayload::Spectators(spectators) => {
+                spectating.spectators = spectators.names;
+            }
+            SpectatePayload::End(end) => {
+                spectating.message = format!("Winner: {}", end.winner);
+            }
+        }
+    }
+}
+
+pub fn spectate_text_system(
+    spectating: Res<Spectating>,
+    mut text: Query<&mut Text, With<SpectateText>>,
+) {
+    let mut value = "Live games  [Enter] watch\n".to_string();
+    for (i, game) in spectating.games.iter().enumerate() {
+        let cursor = if i == spectating.selected { ">" } else { " " };
+        let kind = match GameKind::from_i32(game.kind) {
+            Some(GameKind::Royale) => "Royale",
+            _ => "Versus",
+        };
+        let players: Vec<&str> = game.players.iter().map(|p| p.name.as_str()).collect();
+        value += &format!(
+            "{} {} #{} {} ({} watching)\n",
+            cursor,
+            kind,
+            game.game_id,
+            players.join(" vs "),
+            game.spectators
+        );
+    }
+    if let Some(game) = &spectating.watching {
+        value += &format!(
+            "\nWatching #{} ({}s delay)\n",
+            game.game_id,
+            spectating.delay.as_secs()
+        );
+        value += &format!("Spectators: {}\n", spectating.spectators.join(", "));
+    }
+    if !spectating.message.is_empty() {
+        value += &format!("\n{}", spectating.message);
+    }
+    text.single_mut().sections[0].value = value;
+}
@@ -0,0 +1,318 @@
+//个人资料界面：历史成绩、各模式的个人最佳与成绩曲线
+use crate::leaderboard::{board_modes, format_date, mode_labels, tabs};
+use crate::net::{self, server_channel};
+use bevy::prelude::*;
+use russia_block::rblock::players_client::PlayersClient;
+use russia_block::rblock::{GameRecord, ModeStats, PlayerStats, PlayerStatsRequest};
+use std::sync::{mpsc, Mutex};
+use tonic::Response;
+
+//个人资料界面：M切换模式，Tab切换曲线的指标，R重新查询
+pub struct StatsView {
+    pub player_id: String,
+    pub mode: usize,
+    pub metric: usize,
+    pub stats: Option<PlayerStats>,
+    pub message: String,
+}
+pub struct StatsLink {
+    pub requests: tokio::sync::mpsc::UnboundedSender<PlayerStatsRequest>,
+    results: Mutex<mpsc::Receiver<Result<PlayerStats, String>>>,
+}
+#[derive(Component)]
+pub struct StatsText;
+//曲线的点、线段与坐标标注，每次更新时全部重建
+#[derive(Component)]
+pub struct StatsGraph;
+
+const STATS_METRICS: [&str; 3] = ["Score", "Lines", "PPS"];
+const STATS_HISTORY: u32 = 200;
+//曲线区域左下角与宽高
+const GRAPH_ORIGIN: (f32, f32) = (-580.0, -280.0);
+const GRAPH_SIZE: (f32, f32) = (820.0, 520.0);
+
+impl StatsView {
+    pub fn request(&self) -> PlayerStatsRequest {
+        PlayerStatsRequest {
+            player_id: self.player_id.clone(),
+            mode: board_modes().nth(self.mode).unwrap().to_string(),
+            history_limit: STATS_HISTORY,
+        }
+    }
+}
+
+fn metric_value(game: &GameRecord, metric: usize) -> f32 {
+    match metric {
+        0 => game.score as f32,
+        1 => game.lines as f32,
+        _ => game.pps,
+    }
+}
+
+pub fn connect_stats() -> StatsLink {
+    let (requests, mut requests_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (results_tx, results) = mpsc::channel();
+    net::spawn(async move {
+        while let Some(request) = requests_rx.recv().await {
+            let result = match server_channel() {
+                Ok(channel) => PlayersClient::new(channel)
+                    .get_player_stats(request)
+                    .await
+                    .map(Response::into_inner)
+                    .map_err(|status| status.message().to_string()),
+                Err(e) => Err(e),
+            };
+            if results_tx.send(result).is_err() {
+                break;
+            }
+        }
+    });
+    StatsLink {
+        requests,
+        results: Mutex::new(results),
+    }
+}
+
+pub fn setup_stats(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    commands.spawn_bundle(UiCameraBundle::default());
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![TextSection {
+                    value: String::new(),
+                    style: TextStyle {
+                        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                        font_size: 18.0,
+                        color: Color::rgb(0.5, 0.5, 1.0),
+                    },
+                }],
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(20.0),
+                    left: Val::Px(940.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(StatsText);
+}
+
+pub fn stats_key_system(
+    key_input: Res<Input<KeyCode>>,
+    link: Res<StatsLink>,
+    mut view: ResMut<StatsView>,
+) {
+    if key_input.just_pressed(KeyCode::Tab) {
+        //只换指标，不必重新查询
+        view.metric = (view.metric + 1) % STATS_METRICS.len();
+        return;
+    } else if key_input.just_pressed(KeyCode::M) {
+        view.mode = (view.mode + 1) % board_modes().count();
+    } else if !key_input.just_pressed(KeyCode::R) {
+        return;
+    }
+    view.message = "loading...".to_string();
+    link.requests.send(view.request()).ok();
+}
+
+pub fn stats_text_system(
+    link: Res<StatsLink>,
+    mut view: ResMut<StatsView>,
+    mut text: Query<&mut Text, With<StatsText>>,
+) {
+    while let Ok(result) = link.results.lock().unwrap().try_recv() {
+        match result {
+            Ok(stats) => {
+                view.stats = Some(stats);
+                view.message.clear();
+            }
+            Err(e) => view.message = e,
+        }
+    }
+    if !view.is_changed() {
+        return;
+    }
+    let mut value = String::new();
+    if let Some(profile) = view.stats.as_ref().and_then(|s| s.profile.as_ref()) {
+        value += &format!(
+            "{}\nid {}\njoined {}\n\n",
+            profile.name,
+            profile.player_id,
+            format_date(profile.registered_at)
+        );
+    }
+    value += "[M] mode\n";
+    value += &tabs(mode_labels(), view.mode);
+    value += "\n[Tab] graph\n";
+    value += &tabs(STATS_METRICS.to_vec(), view.metric);
+    value += "\n[R] refresh\n\n";
+    if let Some(stats) = &view.stats {
+        let overall = stats.overall.clone().unwrap_or_default();
+        let played = overall.total_duration_ms / 1000;
+        value += &format!(
+            "Games      {:>9}\nBest score {:>9}\n           {:>9}\nBest lines {:>9}\nBest PPS   {:>9.2}\nAvg score  {:>9.0}\nLines      {:>9}\nPlay time  {:>5}h{:02}m\n",
+            overall.games,
+            overall.best_score,
+            match overall.games {
+                0 => String::new(),
+                _ => format_date(overall.best_score_at),
+            },
+            overall.best_lines,
+            overall.best_pps,
+            overall.average_score,
+            overall.total_lines,
+            played / 3600,
+            played % 3600 / 60
+        );
+        if stats.modes.len() > 1 {
+            value += "\nmode      games    best\n";
+            for ModeStats {
+                mode,
+                games,
+                best_score,
+                ..
+            } in &stats.modes
+            {
+                value += &format!("{:<9}{:>6}{:>8}\n", mode, games, best_score);
+            }
+        }
+        if stats.total_games as usize > stats.history.len() {
+            value += &format!("\ngraph shows last {} games\n", stats.history.len());
+        }
+    }
+    if !view.message.is_empty() {
+        value += &format!("\n{}", view.message);
+    }
+    text.single_mut().sections[0].value = value;
+}
+
+//a到b的线段
+fn segment(a: Vec2, b: Vec2, width: f32, color: Color) -> SpriteBundle {
+    let delta = b - a;
+    SpriteBundle {
+        sprite: Sprite {
+            color,
+            custom_size: Some(Vec2::new(delta.length() + width, width)),
+            ..Default::default()
+        },
+        transform: Transform {
+            translation: ((a + b) / 2.0).extend(0.0),
+            rotation: Quat::from_rotation_z(delta.y.atan2(delta.x)),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+//按局绘制所选指标，金色阶梯线为截至每局的个人最佳
+pub fn stats_graph_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    view: Res<StatsView>,
+    marks: Query<Entity, With<StatsGraph>>,
+) {
+    if !view.is_changed() {
+        return;
+    }
+    for entity in marks.iter() {
+        commands.entity(entity).despawn();
+    }
+    let origin = Vec2::new(GRAPH_ORIGIN.0, GRAPH_ORIGIN.1);
+    let size = Vec2::new(GRAPH_SIZE.0, GRAPH_SIZE.1);
+    let axis = Color::rgb(0.6, 0.6, 0.6);
+    for end in [
+        origin + Vec2::new(size.x, 0.0),
+        origin + Vec2::new(0.0, size.y),
+    ] {
+        commands
+            .spawn_bundle(segment(origin, end, 2.0, axis))
+            .insert(StatsGraph);
+    }
+    let style = TextStyle {
+        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+        font_size: 16.0,
+        color: axis,
+    };
+    let mut label = |value: String, at: Vec2, horizontal: HorizontalAlign| {
+        let alignment = TextAlignment {
+            vertical: VerticalAlign::Center,
+            horizontal,
+        };
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::with_section(value, style.clone(), alignment),
+                transform: Transform::from_translation(at.extend(1.0)),
+                ..Default::default()
+            })
+            .insert(StatsGraph);
+    };
+    let games = match &view.stats {
+        Some(stats) if !stats.history.is_empty() => &stats.history,
+        Some(_) => {
+            label(
+                "No games yet".to_string(),
+                origin + size / 2.0,
+                HorizontalAlign::Center,
+            );
+            return;
+        }
+        None => return,
+    };
+    let values: Vec<f32> = games.iter().map(|g| metric_value(g, view.metric)).collect();
+    let max = values.iter().cloned().fold(0.0, f32::max).max(1.0);
+    let step = size.x / (games.len() - 1).max(1) as f32;
+    let point = |i: usize, value: f32| origin + Vec2::new(step * i as f32, size.y * value / max);
+    let precision = if view.metric == 2 { 2 } else { 0 };
+    label(
+        format!("{:.*}", precision, max),
+        origin + Vec2::new(-8.0, size.y),
+        HorizontalAlign::Right,
+    );
+    label(
+        "0".to_string(),
+        origin + Vec2::new(-8.0, 0.0),
+        HorizontalAlign::Right,
+    );
+    label(
+        format_date(games[0].played_at),
+        origin + Vec2::new(0.0, -20.0),
+        HorizontalAlign::Left,
+    );
+    label(
+        format_date(games[games.len() - 1].played_at),
+        origin + Vec2::new(size.x, -20.0),
+        HorizontalAlign::Right,
+    );
+
+    let line = Color::rgb(0.5, 0.5, 1.0);
+    let best_color = Color::rgb(0.9, 0.7, 0.1);
+    let mut best = values[0];
+    for i in 0..values.len() {
+        let here = point(i, values[i]);
+        if i + 1 < values.len() {
+            commands
+                .spawn_bundle(segment(here, point(i + 1, values[i + 1]), 2.0, line))
+                .insert(StatsGraph);
+            let next_best = best.max(values[i + 1]);
+            let corner = point(i + 1, best);
+            commands
+                .spawn_bundle(segment(point(i, best), corner, 2.0, best_color))
+                .insert(StatsGraph);
+            if next_best > best {
+                commands
+                    .spawn_bundle(segment(corner, point(i + 1, next_best), 2.0, best_color))
+                    .insert(StatsGraph);
+            }
+            best = next_best;
+        }
+        commands
+            .spawn_bundle(segment(here, here, 6.0, line))
+            .insert(StatsGraph);
+    }
+}
@@ -0,0 +1,138 @@
+//按键精简度统计与练习模式的目标落点提示
+use crate::game::{block_sprite, cell_translation, RestartEvent, ROW_NUM};
+use bevy::prelude::*;
+use russia_block::bot;
+use russia_block::engine::{Engine, Input as GameInput};
+use russia_block::finesse::{self, Finesse};
+
+//按键精简度统计，对局结束后显示汇总
+#[derive(Component)]
+pub struct FinesseText;
+//练习模式：为当前方块提示最优落点及其最少按键
+#[derive(Default)]
+pub struct TrainingTarget {
+    //提示对应的方块序号(Finesse::pieces)
+    piece: Option<u32>,
+    keys: Vec<GameInput>,
+}
+#[derive(Component)]
+pub struct TargetCell;
+
+pub fn setup_finesse(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = TextStyle {
+        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+        font_size: 24.0,
+        color: Color::rgb(1.0, 0.5, 0.5),
+    };
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: String::new(),
+                        style: style.clone(),
+                    },
+                    //练习模式的目标按键
+                    TextSection {
+                        value: String::new(),
+                        style: TextStyle {
+                            color: Color::rgb(1.0, 0.9, 0.2),
+                            ..style
+                        },
+                    },
+                ],
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(55.0),
+                    left: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(FinesseText);
+}
+
+//实时显示失误数，顶出后换成本局汇总
+pub fn finesse_text_system(
+    engine: Res<Engine>,
+    finesse: Res<Finesse>,
+    mut query: Query<&mut Text, With<FinesseText>>,
+) {
+    if !finesse.is_changed() {
+        return;
+    }
+    let mut text = query.single_mut();
+    text.sections[0].value = if engine.topped_out() {
+        format!(
+            "Finesse: {} faults in {} pieces\n{} extra keys, accuracy {:.0}%",
+            finesse.faults,
+            finesse.pieces,
+            finesse.extra,
+            finesse.accuracy() * 100.0
+        )
+    } else {
+        format!(
+            "Finesse faults: {}/{} (+{} keys)",
+            finesse.faults, finesse.pieces, finesse.extra
+        )
+    };
+}
+
+pub fn setup_training(mut commands: Commands) {
+    for _ in 0..4 {
+        let mut sprite = block_sprite(Vec3::ZERO, Color::rgba(1.0, 0.9, 0.2, 0.5));
+        sprite.visibility.is_visible = false;
+        commands.spawn_bundle(sprite).insert(TargetCell);
+    }
+}
+
+//每个新方块出场时用内置AI的评分选出目标落点，高亮其格子并提示最少按键
+pub fn training_system(
+    finesse: Res<Finesse>,
+    mut target: ResMut<TrainingTarget>,
+    mut cells: Query<(&mut Transform, &mut Visibility), With<TargetCell>>,
+    mut text: Query<&mut Text, With<FinesseText>>,
+    mut restart: EventReader<RestartEvent>,
+) {
+    if restart.iter().count() > 0 {
+        target.piece = None;
+    }
+    if target.piece == Some(finesse.pieces) {
+        return;
+    }
+    target.piece = Some(finesse.pieces);
+    //顶出后出场局面已结束，没有落点
+    let best = bot::placements(finesse.start())
+        .into_iter()
+        .max_by(|a, b| a.score.total_cmp(&b.score));
+    let best = match best {
+        Some(best) => best,
+        None => {
+            for (_, mut visibility) in cells.iter_mut() {
+                visibility.is_visible = false;
+            }
+            text.single_mut().sections[1].value.clear();
+            return;
+        }
+    };
+    target.keys = finesse::minimal_keys(finesse.start(), &best.cells).unwrap_or_default();
+    for ((mut transform, mut visibility), &(col, row)) in cells.iter_mut().zip(best.cells.iter()) {
+        //画在方块之上，半透明
+        transform.translation = cell_translation(col, row) + Vec3::new(0.0, 0.0, 0.1);
+        visibility.is_visible = row < ROW_NUM as i32;
+    }
+    let keys: Vec<String> = target.keys.iter().map(|key| format!("{:?}", key)).collect();
+    text.single_mut().sections[1].value = format!(
+        "\nTarget: {}",
+        if keys.is_empty() {
+            "Drop".to_string()
+        } else {
+            keys.join(" ")
+        }
+    );
+}
@@ -0,0 +1,369 @@
+//1v1对战：对战流、对手的缩小棋盘、垃圾行计量条与观众名单
+use crate::game::{
+    AttackEvent, InputQueue, PauseControl, PieceLockedEvent, TopOutEvent, COL_NUM, ROW_NUM,
+};
+use crate::net::{authorize, server_channel, StreamLink};
+use crate::profile::{ensure_session, Profile};
+use bevy::prelude::*;
+use russia_block::engine::{Cell, Engine, Input as GameInput};
+use russia_block::rblock::versus_client::VersusClient;
+use russia_block::rblock::versus_event::Payload as EventPayload;
+use russia_block::rblock::versus_message::Payload as MessagePayload;
+use russia_block::rblock::{
+    Attack, Board, PieceLocked, Spectators, TopOut, VersusEvent, VersusJoin, VersusMessage,
+};
+use std::sync::{Arc, Mutex};
+use tonic::Request;
+
+type VersusLink = StreamLink<VersusMessage, VersusEvent>;
+
+#[derive(Default)]
+pub struct VersusState {
+    opponent: Option<String>,
+    rating: u32,
+    opponent_rating: u32,
+    finished: bool,
+}
+#[derive(Component)]
+pub struct VersusStatus;
+#[derive(Component)]
+pub struct RatingBoardText;
+#[derive(Component)]
+pub struct OpponentCell {
+    pub col: usize,
+    pub row: usize,
+}
+
+//对局中显示的观众名单
+#[derive(Component)]
+struct SpectatorList;
+
+#[derive(Component)]
+struct GarbageMeter;
+
+const OPPONENT_ORIGIN: (f32, f32) = (300.0, -175.0); //对手缩小棋盘左下角
+
+//对局中显示正在观战的观众
+pub fn add_spectator_list(app: &mut App) {
+    app.insert_resource(Spectators::default())
+        .add_startup_system(setup_spectator_list)
+        .add_system(spectator_list_system);
+}
+
+//对战类模式的垃圾行计量条，攻击与垃圾行由引擎结算
+pub fn add_garbage_systems(app: &mut App) {
+    app.add_startup_system(setup_garbage_meter)
+        .add_system(garbage_meter_system);
+}
+
+//对战按登录的玩家匹配与计分，还没有注册时先注册
+pub fn connect_versus(profile: Arc<Mutex<Profile>>) -> VersusLink {
+    let link = StreamLink::connect(|outgoing| async move {
+        let token = ensure_session(&profile).await?;
+        let mut client = VersusClient::new(server_channel()?);
+        let mut request = Request::new(outgoing);
+        authorize(&mut request, &token);
+        Ok(client.play(request).await?.into_inner())
+    });
+    link.send(VersusMessage {
+        payload: Some(MessagePayload::Join(VersusJoin::default())),
+    });
+    link
+}
+
+//将引擎中的棋盘编码为对战消息中的棋盘快照
+pub fn board_snapshot(engine: &Engine) -> Board {
+    let mut board = Board {
+        rows: vec![0; ROW_NUM],
+        alive: Vec::new(),
+    };
+    for (row, cells) in engine.board().iter().enumerate().take(ROW_NUM) {
+        for (col, cell) in cells.iter().enumerate() {
+            if *cell != Cell::Empty {
+                board.rows[row] |= 1 << col;
+            }
+        }
+    }
+    for &(col, row) in engine.piece().iter() {
+        if (0..ROW_NUM as i32).contains(&row) {
+            board.alive.push(row as u32 * COL_NUM as u32 + col as u32);
+        }
+    }
+    board
+}
+
+//缩小绘制的棋盘中某一格的颜色
+pub fn cell_color(board: &Board, col: usize, row: usize) -> Color {
+    if board.alive.contains(&((row * COL_NUM + col) as u32)) {
+        Color::rgb(0.5, 0.5, 0.5)
+    } else if board.rows.get(row).copied().unwrap_or(0) & 1 << col != 0 {
+        Color::rgb(0.5, 0.7, 0.2)
+    } else {
+        Color::rgb(0.8, 0.8, 0.8)
+    }
+}
+
+fn setup_spectator_list(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![TextSection {
+                    value: String::new(),
+                    style: TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 20.0,
+                        color: Color::rgb(0.5, 0.5, 1.0),
+                    },
+                }],
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(620.0),
+                    left: Val::Px(940.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(SpectatorList);
+}
+
+fn spectator_list_system(
+    spectators: Res<Spectators>,
+    mut text: Query<&mut Text, With<SpectatorList>>,
+) {
+    if !spectators.is_changed() {
+        return;
+    }
+    text.single_mut().sections[0].value = if spectators.names.is_empty() {
+        String::new()
+    } else {
+        format!("Spectators: {}", spectators.names.join(", "))
+    };
+}
+
+pub fn setup_versus(mut commands: Commands, asset_server: Res<AssetServer>) {
+    //对手棋盘，按一半尺寸绘制在己方棋盘右侧
+    for col in 0..COL_NUM {
+        for row in 0..ROW_NUM {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    transform: Transform {
+                        translation: Vec3::new(
+                            OPPONENT_ORIGIN.0 + 17.5 * col as f32 + 8.75,
+                            OPPONENT_ORIGIN.1 + 17.5 * row as f32 + 8.75,
+                            0.0,
+                        ),
+                        scale: Vec3::new(15.0, 15.0, 0.0),
+                        ..Default::default()
+                    },
+                    sprite: Sprite {
+                        color: Color::rgb(0.8, 0.8, 0.8),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(OpponentCell { col, row });
+        }
+    }
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![TextSection {
+                    value: "Waiting for opponent...".to_string(),
+                    style: TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 30.0,
+                        color: Color::rgb(0.5, 0.5, 1.0),
+                    },
+                }],
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(140.0),
+                    left: Val::Px(940.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(VersusStatus);
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text {
+                sections: vec![TextSection {
+                    value: String::new(),
+                    style: TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 20.0,
+                        color: Color::rgb(0.5, 0.5, 1.0),
+                    },
+                }],
+                ..Default::default()
+            },
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(260.0),
+                    left: Val::Px(940.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(RatingBoardText);
+}
+
+pub fn versus_board_system(link: Res<VersusLink>, state: Res<VersusState>, engine: Res<Engine>) {
+    if state.opponent.is_none() || state.finished {
+        return;
+    }
+    let board = board_snapshot(&engine);
+    link.send(VersusMessage {
+        payload: Some(MessagePayload::Board(board)),
+    });
+}
+
+pub fn versus_send_system(
+    link: Res<VersusLink>,
+    mut state: ResMut<VersusState>,
+    engine: Res<Engine>,
+    mut locked: EventReader<PieceLockedEvent>,
+    mut attacks: EventReader<AttackEvent>,
+    mut top_out: EventReader<TopOutEvent>,
+) {
+    if state.opponent.is_none() || state.finished {
+        return;
+    }
+    for event in locked.iter() {
+        link.send(VersusMessage {
+            payload: Some(MessagePayload::Locked(PieceLocked {
+                lines: event.lines as u32,
+                score: engine.score(),
+            })),
+        });
+    }
+    for attack in attacks.iter() {
+        link.send(VersusMessage {
+            payload: Some(MessagePayload::Attack(Attack {
+                lines: attack.lines,
+            })),
+        });
+    }
+    if top_out.iter().next().is_some() {
+        state.finished = true;
+        link.send(VersusMessage {
+            payload: Some(MessagePayload::TopOut(TopOut {})),
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn versus_receive_system(
+    link: Res<VersusLink>,
+    mut state: ResMut<VersusState>,
+    mut pause: ResMut<PauseControl>,
+    mut queue: ResMut<InputQueue>,
+    mut spectators: ResMut<Spectators>,
+    mut status: Query<&mut Text, (With<VersusStatus>, Without<RatingBoardText>)>,
+    mut board_text: Query<&mut Text, (With<RatingBoardText>, Without<VersusStatus>)>,
+    mut cells: Query<(&OpponentCell, &mut Sprite)>,
+) {
+    let incoming = link.incoming.lock().unwrap();
+    let mut text = status.single_mut();
+    while let Ok(VersusEvent {
+        payload: Some(payload),
+    }) = incoming.try_recv()
+    {
+        match payload {
+            EventPayload::Matched(matched) => {
+                text.sections[0].value = format!(
+                    "({}) VS {} ({})",
+                    matched.rating, matched.opponent, matched.opponent_rating
+                );
+                state.rating = matched.rating;
+                state.opponent_rating = matched.opponent_rating;
+                state.opponent = Some(matched.opponent);
+                pause.pause = false;
+            }
+            EventPayload::OpponentBoard(board) => {
+                for (cell, mut sprite) in cells.iter_mut() {
+                    sprite.color = cell_color(&board, cell.col, cell.row);
+                }
+            }
+            EventPayload::OpponentLocked(locked) => {
+                if let Some(opponent) = &state.opponent {
+                    text.sections[0].value = format!(
+                        "({}) VS {} ({}) score:{}",
+                        state.rating, opponent, state.opponent_rating, locked.score
+                    );
+                }
+            }
+            EventPayload::Garbage(attack) => {
+                if attack.lines > 0 {
+                    queue.pending.push_back(GameInput::Garbage(attack.lines));
+                }
+            }
+            EventPayload::GameOver(over) => {
+                state.finished = true;
+                pause.pause = true;
+                text.sections[0].value = format!(
+                    "{} {} -> {} ({:+})",
+                    if over.win { "You Win!" } else { "You Lose!" },
+                    state.rating,
+                    over.rating,
+                    over.rating_change
+                );
+                state.rating = over.rating;
+            }
+            EventPayload::Ratings(board) => {
+                //评分排行榜
+                let mut value = "Rating\n".to_string();
+                for (i, player) in board.players.iter().enumerate() {
+                    value += &format!(
+                        "{}. {} {} ({}/{})\n",
+                        i + 1,
+                        player.name,
+                        player.rating,
+                        player.wins,
+                        player.games
+                    );
+                }
+                board_text.single_mut().sections[0].value = value;
+            }
+            EventPayload::Spectators(list) => *spectators = list,
+        }
+    }
+}
+
+fn setup_garbage_meter(mut commands: Commands) {
+    //垃圾行计量条，位于棋盘左侧
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform {
+                translation: Vec3::new(-35.0 * 6.0 - 15.0, -35.0 * 10.0, 0.0),
+                scale: Vec3::new(10.0, 0.0, 1.0),
+                ..Default::default()
+            },
+            sprite: Sprite {
+                color: Color::rgb(0.9, 0.2, 0.2),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(GarbageMeter);
+}
+
+fn garbage_meter_system(engine: Res<Engine>, mut meter: Query<&mut Transform, With<GarbageMeter>>) {
+    let height = 35.0 * engine.pending_garbage().min(ROW_NUM as u32) as f32;
+    let mut transform = meter.single_mut();
+    transform.scale.y = height;
+    transform.translation.y = -35.0 * 10.0 + height / 2.0;
+}
@@ -0,0 +1,243 @@
+//服务端配置：默认值 < 配置文件(TOML) < 环境变量 < 命令行参数，后者覆盖前者
+use crate::tls::TlsOptions;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+//未用--config或RBLOCK_CONFIG指定时，存在则读取
+pub const DEFAULT_CONFIG: &str = "server.toml";
+const CONFIG_ENV: &str = "RBLOCK_CONFIG";
+//单次排行榜查询条数上限的允许范围
+const LEADERBOARD_MAX_LIMIT: u32 = 1000;
+
+//(命令行参数, 环境变量)，与Config::set的键一一对应
+const SETTINGS: [(&str, &str); 9] = [
+    ("--bind", "RBLOCK_BIND"),
+    ("--storage", "RBLOCK_STORAGE"),
+    ("--leaderboard-max", "RBLOCK_LEADERBOARD_MAX"),
+    ("--log-level", "RBLOCK_LOG"),
+    ("--rate-limit", "RBLOCK_RATE_LIMIT"),
+    ("--rate-burst", "RBLOCK_RATE_BURST"),
+    ("--tls-cert", "RBLOCK_TLS_CERT"),
+    ("--tls-key", "RBLOCK_TLS_KEY"),
+    ("--tls-client-ca", "RBLOCK_TLS_CLIENT_CA"),
+];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind: String,
+    //日志文件路径，memory表示不落盘
+    pub storage: String,
+    //单次排行榜查询最多返回的条数
+    pub leaderboard_max: u32,
+    //env_logger格式，如info或server=debug
+    pub log_level: String,
+    pub rate_limit: RateLimit,
+    pub tls: TlsOptions,
+}
+
+//按客户端IP的令牌桶，requests_per_second为0时不限速
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimit {
+    pub requests_per_second: u32,
+    pub burst: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: "127.0.0.1:8020".to_string(),
+            storage: "rblock.log".to_string(),
+            leaderboard_max: 100,
+            log_level: "info".to_string(),
+            rate_limit: RateLimit::default(),
+            tls: TlsOptions::default(),
+        }
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit {
+            requests_per_second: 20,
+            burst: 40,
+        }
+    }
+}
+
+//命令行解析结果
+pub struct Options {
+    pub config: Config,
+    pub print_config: bool,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    //按参数名设置一项，key为不带--的参数名
+    fn set(&mut self, key: &str, value: String) -> Result<(), String> {
+        let number = |value: &str| {
+            u32::from_str(value).map_err(|_| format!("{} expects a number, got {:?}", key, value))
+        };
+        match key {
+            "bind" => self.bind = value,
+            "storage" => self.storage = value,
+            "leaderboard-max" => self.leaderboard_max = number(&value)?,
+            "log-level" => self.log_level = value,
+            "rate-limit" => self.rate_limit.requests_per_second = number(&value)?,
+            "rate-burst" => self.rate_limit.burst = number(&value)?,
+            "tls-cert" => self.tls.cert = Some(value.into()),
+            "tls-key" => self.tls.key = Some(value.into()),
+            "tls-client-ca" => self.tls.client_ca = Some(value.into()),
+            _ => return Err(format!("unknown setting {}", key)),
+        }
+        Ok(())
+    }
+
+    pub fn bind_addr(&self) -> Result<SocketAddr, String> {
+        self.bind
+            .parse()
+            .map_err(|_| format!("bind: invalid address {:?}", self.bind))
+    }
+
+    //列出全部不合法的设置
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        if let Err(e) = self.bind_addr() {
+            errors.push(e);
+        }
+        if self.storage.is_empty() {
+            errors.push("storage: path is empty".to_string());
+        }
+        if !(1..=LEADERBOARD_MAX_LIMIT).contains(&self.leaderboard_max) {
+            errors.push(format!(
+                "leaderboard_max: must be between 1 and {}",
+                LEADERBOARD_MAX_LIMIT
+            ));
+        }
+        //每一项为"级别"或"模块=级别"
+        let levels_valid = self.log_level.split(',').all(|directive| {
+            let level = directive.rsplit('=').next().unwrap_or_default();
+            log::LevelFilter::from_str(level.trim()).is_ok()
+        });
+        if !levels_valid {
+            errors.push(format!("log_level: invalid filter {:?}", self.log_level));
+        }
+        if self.rate_limit.requests_per_second > 0 && self.rate_limit.burst == 0 {
+            errors.push("rate_limit.burst: must be at least 1 when rate limiting".to_string());
+        }
+        if let Err(e) = self.tls.server_config() {
+            errors.push(format!("tls: {}", e));
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors.join("\n")),
+        }
+    }
+}
+
+//解析命令行与环境变量并校验；--config指定的文件必须存在，默认文件不存在时忽略
+pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut path = std::env::var(CONFIG_ENV).ok();
+    let mut print_config = false;
+    let mut overrides = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--print-config" => print_config = true,
+            "--config" => path = Some(args.next().ok_or("--config needs a path")?),
+            flag if SETTINGS.iter().any(|(name, _)| *name == flag) => {
+                let value = args.next().ok_or(format!("{} needs a value", flag))?;
+                overrides.push((flag.trim_start_matches("--").to_string(), value));
+            }
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    let mut config = match path {
+        Some(path) => Config::load(path.as_ref())?,
+        None if Path::new(DEFAULT_CONFIG).exists() => Config::load(DEFAULT_CONFIG.as_ref())?,
+        None => Config::default(),
+    };
+    for (flag, env) in SETTINGS {
+        if let Ok(value) = std::env::var(env) {
+            config.set(flag.trim_start_matches("--"), value)?;
+        }
+    }
+    for (key, value) in overrides {
+        config.set(&key, value)?;
+    }
+    config.validate()?;
+    Ok(Options {
+        config,
+        print_config,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_is_valid() {
+        Config::default().validate().unwrap();
+    }
+
+    #[test]
+    fn every_invalid_setting_is_reported() {
+        let mut config = Config::default();
+        config.set("bind", "localhost".to_string()).unwrap();
+        config.set("storage", String::new()).unwrap();
+        config.set("leaderboard-max", "0".to_string()).unwrap();
+        config
+            .set("log-level", "info,server=loud".to_string())
+            .unwrap();
+        config.set("rate-burst", "0".to_string()).unwrap();
+        config.set("tls-cert", "server.pem".to_string()).unwrap();
+        let e = config.validate().unwrap_err();
+        let fields: Vec<&str> = e
+            .lines()
+            .map(|line| line.split(':').next().unwrap())
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                "bind",
+                "storage",
+                "leaderboard_max",
+                "log_level",
+                "rate_limit.burst",
+                "tls"
+            ]
+        );
+    }
+
+    #[test]
+    fn module_filters_and_disabled_rate_limit_are_valid() {
+        let mut config = Config::default();
+        config
+            .set("log-level", "warn,server=debug".to_string())
+            .unwrap();
+        config.set("rate-limit", "0".to_string()).unwrap();
+        config.set("rate-burst", "0".to_string()).unwrap();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn bad_values_and_keys_are_rejected() {
+        let mut config = Config::default();
+        let e = config.set("rate-limit", "fast".to_string()).unwrap_err();
+        assert!(e.contains("rate-limit"), "{}", e);
+        assert!(config.set("port", "1".to_string()).is_err());
+        let e = toml::from_str::<Config>("bind = \"0.0.0.0:1\"\nport = 1").unwrap_err();
+        assert!(e.to_string().contains("port"), "{}", e);
+        let config: Config = toml::from_str("[rate_limit]\nburst = 5").unwrap();
+        assert_eq!(config.rate_limit.burst, 5);
+        assert_eq!(config.rate_limit.requests_per_second, 20);
+    }
+}
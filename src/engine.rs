@@ -0,0 +1,509 @@
+//无渲染的规则引擎：方块序列、移动、落定、消行、计分、攻击与垃圾行都在这里结算。
+//客户端按引擎状态绘制棋盘，服务端用它重放录像核对分数，同一种子与输入序列总得到同一结果
+use crate::rblock::{InputEvent, InputKind};
+use rand::prng::ChaChaRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+
+//规则变化后旧录像无法重现，录像里记下规则版本
+pub const REPLAY_VERSION: u32 = 2;
+pub const COLS: usize = 12;
+pub const ROWS: usize = 20;
+//单次攻击与待接收垃圾行的上限，超出部分直接丢弃
+pub const GARBAGE_MAX: u32 = ROWS as u32;
+//落定后有方块到达此行即顶出
+const TOP_OUT_ROW: usize = ROWS - 2;
+//新方块的旋转中心，以半格为单位(第5.5列、第17.5行)
+const SPAWN_CENTER: (i32, i32) = (11, 35);
+
+//围绕旋转中心构造的7种基本图案，以半格为单位
+const SHAPES: [[(i32, i32); 4]; 7] = [
+    [(1, 1), (-1, 1), (1, 3), (-1, -1)],   //N
+    [(1, 1), (-1, 1), (1, -1), (-1, 3)],   //N
+    [(1, 1), (-1, 1), (-1, -1), (-1, 3)],  //T
+    [(-1, 1), (-1, -1), (1, -1), (1, 1)],  //O
+    [(1, -1), (-1, 3), (-1, -1), (-1, 1)], //L
+    [(-1, -1), (1, 3), (1, -1), (1, 1)],   //L
+    [(1, 1), (1, 3), (1, -1), (1, -3)],    //I
+];
+
+//攻击表：普通消除按行数，T-spin按行数，连击(combo)与连续高难度消除(B2B)额外加成
+const LINE_ATTACK: [u32; 5] = [0, 0, 1, 2, 4];
+const TSPIN_ATTACK: [u32; 4] = [0, 2, 4, 6];
+const COMBO_ATTACK: [u32; 12] = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+//一次落定最多打出的攻击行数：T-spin三消加B2B与最高连击
+pub const ATTACK_MAX: u32 = TSPIN_ATTACK[3] + 1 + COMBO_ATTACK[COMBO_ATTACK.len() - 1];
+
+//作用于棋盘的一次输入，Garbage为收到的垃圾行数
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Input {
+    Left,
+    Right,
+    SoftDrop,
+    Rotate,
+    Gravity,
+    Garbage(u32),
+}
+
+impl Input {
+    pub fn from_event(event: &InputEvent) -> Option<Input> {
+        Some(match InputKind::from_i32(event.kind)? {
+            InputKind::Left => Input::Left,
+            InputKind::Right => Input::Right,
+            InputKind::SoftDrop => Input::SoftDrop,
+            InputKind::Rotate => Input::Rotate,
+            InputKind::Gravity => Input::Gravity,
+            InputKind::Garbage => Input::Garbage(event.lines),
+        })
+    }
+
+    pub fn to_event(self, delta_ms: u32) -> InputEvent {
+        let (kind, lines) = match self {
+            Input::Left => (InputKind::Left, 0),
+            Input::Right => (InputKind::Right, 0),
+            Input::SoftDrop => (InputKind::SoftDrop, 0),
+            Input::Rotate => (InputKind::Rotate, 0),
+            Input::Gravity => (InputKind::Gravity, 0),
+            Input::Garbage(lines) => (InputKind::Garbage, lines),
+        };
+        InputEvent {
+            delta_ms,
+            kind: kind as i32,
+            lines,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cell {
+    Empty,
+    Block,
+    Garbage,
+}
+
+//一次落定的结果，cells为方块落定的格子，attack为抵消自己待接收的垃圾行后发给对手的行数
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lock {
+    pub cells: [(i32, i32); 4],
+    pub lines: u32,
+    pub tspin: bool,
+    pub attack: u32,
+}
+
+#[derive(Clone, Default)]
+struct AttackState {
+    combo: Option<usize>,
+    b2b: bool,
+}
+
+impl AttackState {
+    //根据一次落定计算攻击行数，同时更新combo与B2B状态
+    fn on_lock(&mut self, lines: usize, tspin: bool) -> u32 {
+        if lines == 0 {
+            self.combo = None;
+            return 0;
+        }
+        let combo = self.combo.map_or(0, |combo| combo + 1);
+        self.combo = Some(combo);
+        let base = if tspin {
+            TSPIN_ATTACK[lines.min(3)]
+        } else {
+            LINE_ATTACK[lines.min(4)]
+        };
+        let difficult = tspin || lines >= 4;
+        let b2b = difficult && self.b2b;
+        self.b2b = difficult;
+        base + b2b as u32 + COMBO_ATTACK[combo.min(COMBO_ATTACK.len() - 1)]
+    }
+}
+
+//待接收的垃圾行，每项为一次攻击的行数
+#[derive(Clone, Default)]
+struct GarbageQueue {
+    pending: VecDeque<u32>,
+}
+
+impl GarbageQueue {
+    fn total(&self) -> u32 {
+        self.pending.iter().sum()
+    }
+
+    //用己方攻击抵消待接收的垃圾行，返回抵消后剩余的攻击
+    fn cancel(&mut self, mut attack: u32) -> u32 {
+        while attack > 0 {
+            match self.pending.front_mut() {
+                Some(lines) if *lines > attack => {
+                    *lines -= attack;
+                    attack = 0;
+                }
+                Some(lines) => {
+                    attack -= *lines;
+                    self.pending.pop_front();
+                }
+                None => break,
+            }
+        }
+        attack
+    }
+}
+
+#[derive(Clone)]
+pub struct Engine {
+    //方块序列与垃圾行缺口各用一个由种子派生的随机数发生器
+    pieces: ChaChaRng,
+    holes: ChaChaRng,
+    //已落定的格子，自底向上，顶部的空行随时去掉
+    board: Vec<[Cell; COLS]>,
+    //当前方块各格的(列,行)与旋转中心(半格)
+    piece: [(i32, i32); 4],
+    center: (i32, i32),
+    next: usize,
+    //最后一次成功的操作是否为旋转，落定时据此判定T-spin
+    rotated: bool,
+    attack: AttackState,
+    garbage: GarbageQueue,
+    score: u32,
+    topped_out: bool,
+}
+
+impl Engine {
+    pub fn new(seed: u64) -> Self {
+        let mut engine = Engine {
+            pieces: ChaChaRng::seed_from_u64(seed),
+            holes: ChaChaRng::seed_from_u64(seed.wrapping_add(1)),
+            board: Vec::new(),
+            piece: [(0, 0); 4],
+            center: SPAWN_CENTER,
+            next: 0,
+            rotated: false,
+            attack: AttackState::default(),
+            garbage: GarbageQueue::default(),
+            score: 0,
+            topped_out: false,
+        };
+        engine.next = engine.pieces.gen_range(0, SHAPES.len());
+        engine.spawn();
+        engine
+    }
+
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    pub fn topped_out(&self) -> bool {
+        self.topped_out
+    }
+
+    pub fn pending_garbage(&self) -> u32 {
+        self.garbage.total()
+    }
+
+    pub fn board(&self) -> &[[Cell; COLS]] {
+        &self.board
+    }
+
+    pub fn piece(&self) -> &[(i32, i32); 4] {
+        &self.piece
+    }
+
+    //next区方块相对其中心的偏移(半格)
+    pub fn next_shape(&self) -> &[(i32, i32); 4] {
+        &SHAPES[self.next]
+    }
+
+    //next区方块出场时所占的格子
+    pub fn next_piece(&self) -> [(i32, i32); 4] {
+        spawn_cells(self.next)
+    }
+
+    //执行一条输入；移动后方块着地则立即落定并返回结算结果。顶出后忽略所有输入
+    pub fn apply(&mut self, input: Input) -> Option<Lock> {
+        if self.topped_out {
+            return None;
+        }
+        let moved = match input {
+            Input::Garbage(lines) => {
+                let lines = lines.min(GARBAGE_MAX - self.garbage.total());
+                if lines > 0 {
+                    self.garbage.pending.push_back(lines);
+                }
+                return None;
+            }
+            Input::Left => self.shift(-1, 0),
+            Input::Right => self.shift(1, 0),
+            Input::SoftDrop | Input::Gravity => self.shift(0, -1),
+            Input::Rotate => self.rotate(),
+        };
+        if moved {
+            self.rotated = input == Input::Rotate;
+        }
+        if self.landed() {
+            Some(self.lock())
+        } else {
+            None
+        }
+    }
+
+    fn filled(&self, col: i32, row: i32) -> bool {
+        col < 0
+            || col >= COLS as i32
+            || row < 0
+            || self
+                .board
+                .get(row as usize)
+                .is_some_and(|cells| cells[col as usize] != Cell::Empty)
+    }
+
+    fn fits(&self, cells: &[(i32, i32); 4]) -> bool {
+        cells.iter().all(|&(col, row)| !self.filled(col, row))
+    }
+
+    //当前方块是否已压在底部或已落定的格子上
+    fn landed(&self) -> bool {
+        self.piece
+            .iter()
+            .any(|&(col, row)| self.filled(col, row - 1))
+    }
+
+    fn shift(&mut self, dc: i32, dr: i32) -> bool {
+        let cells = self.piece.map(|(col, row)| (col + dc, row + dr));
+        if !self.fits(&cells) {
+            return false;
+        }
+        self.piece = cells;
+        self.center = (self.center.0 + 2 * dc, self.center.1 + 2 * dr);
+        true
+    }
+
+    //绕旋转中心逆时针转90度，越界或与已落定的格子重叠则不转
+    fn rotate(&mut self) -> bool {
+        let (cx, cy) = self.center;
+        let cells = self
+            .piece
+            .map(|(col, row)| ((cx + cy - 2 * row) / 2, (2 * col - cx + cy) / 2));
+        if !self.fits(&cells) {
+            return false;
+        }
+        self.piece = cells;
+        true
+    }
+
+    //T形块的中心格在方块内有三个相邻格；其四个对角中至少三个被占用(含棋盘边界)即为T-spin
+    fn is_tspin(&self) -> bool {
+        let cells = &self.piece;
+        let neighbors = |&(col, row): &(i32, i32)| {
+            cells
+                .iter()
+                .filter(|&&(c, r)| (c - col).abs() + (r - row).abs() == 1)
+                .count()
+        };
+        let center = match cells.iter().find(|cell| neighbors(cell) == 3) {
+            Some(center) => *center,
+            None => return false,
+        };
+        [(-1, -1), (-1, 1), (1, -1), (1, 1)]
+            .iter()
+            .filter(|(dc, dr)| self.filled(center.0 + dc, center.1 + dr))
+            .count()
+            >= 3
+    }
+
+    //落定：消行计分，结算攻击，未消行时插入待接收的垃圾行，最后检查顶出并换下一个方块
+    fn lock(&mut self) -> Lock {
+        let tspin = self.rotated && self.is_tspin();
+        let cells = self.piece;
+        for &(col, row) in cells.iter() {
+            let row = row as usize;
+            if self.board.len() <= row {
+                self.board.resize(row + 1, [Cell::Empty; COLS]);
+            }
+            self.board[row][col as usize] = Cell::Block;
+        }
+        let before = self.board.len();
+        self.board.retain(|cells| cells.contains(&Cell::Empty));
+        let lines = (before - self.board.len()) as u32;
+        self.score += lines * lines;
+
+        //先抵消自己待接收的垃圾行，剩余部分才发给对手
+        let attack = self
+            .garbage
+            .cancel(self.attack.on_lock(lines as usize, tspin));
+        if lines == 0 && !self.garbage.pending.is_empty() {
+            let mut rows = Vec::new();
+            for batch in self.garbage.pending.drain(..) {
+                //同一次攻击的垃圾行共用一个缺口
+                let hole = self.holes.gen_range(0, COLS);
+                let mut cells = [Cell::Garbage; COLS];
+                cells[hole] = Cell::Empty;
+                rows.extend(std::iter::repeat_n(cells, batch as usize));
+            }
+            rows.append(&mut self.board);
+            self.board = rows;
+        }
+        while self
+            .board
+            .last()
+            .is_some_and(|cells| cells.iter().all(|&cell| cell == Cell::Empty))
+        {
+            self.board.pop();
+        }
+
+        self.topped_out = self.board.len() > TOP_OUT_ROW;
+        if !self.topped_out {
+            self.spawn();
+        }
+        Lock {
+            cells,
+            lines,
+            tspin,
+            attack,
+        }
+    }
+
+    //取next区方块放到棋盘顶端并抽下一个；出生即被挡住或着地视为顶出
+    fn spawn(&mut self) {
+        self.piece = spawn_cells(self.next);
+        self.center = SPAWN_CENTER;
+        self.rotated = false;
+        self.next = self.pieces.gen_range(0, SHAPES.len());
+        if !self.fits(&self.piece) || self.landed() {
+            self.topped_out = true;
+        }
+    }
+}
+
+fn spawn_cells(shape: usize) -> [(i32, i32); 4] {
+    let (cx, cy) = SPAWN_CENTER;
+    SHAPES[shape].map(|(dx, dy)| ((cx + dx) / 2, (cy + dy) / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const I: usize = 6;
+
+    //出场方块换成指定图案，I形块竖放在第6列第16至19行
+    fn engine_with(shape: usize, board: Vec<[Cell; COLS]>) -> Engine {
+        let mut engine = Engine::new(0);
+        engine.board = board;
+        engine.piece = spawn_cells(shape);
+        engine.center = SPAWN_CENTER;
+        engine
+    }
+
+    fn hard_drop(engine: &mut Engine) -> Lock {
+        loop {
+            if let Some(lock) = engine.apply(Input::SoftDrop) {
+                return lock;
+            }
+        }
+    }
+
+    fn sorted(cells: &[(i32, i32); 4]) -> Vec<(i32, i32)> {
+        let mut cells = cells.to_vec();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn clearing_lines_scores_and_drops_the_rest() {
+        let mut row = [Cell::Block; COLS];
+        row[6] = Cell::Empty;
+        let mut engine = engine_with(I, vec![row, row]);
+        let lock = hard_drop(&mut engine);
+        assert_eq!((lock.lines, lock.tspin, lock.attack), (2, false, 1));
+        assert_eq!(engine.score(), 4);
+        //I形块剩下的两格落到底部
+        assert_eq!(engine.board().len(), 2);
+        for cells in engine.board() {
+            let filled: Vec<usize> = (0..COLS).filter(|&c| cells[c] != Cell::Empty).collect();
+            assert_eq!(filled, vec![6]);
+        }
+    }
+
+    #[test]
+    fn attack_table_with_combo_and_b2b() {
+        let mut attack = AttackState::default();
+        assert_eq!(attack.on_lock(1, false), 0);
+        //第二次连续消除，combo加成为0
+        assert_eq!(attack.on_lock(4, false), 4);
+        //B2B加1，第三次连续消除combo加1
+        assert_eq!(attack.on_lock(4, false), 6);
+        assert_eq!(attack.on_lock(0, false), 0);
+        //未消行打断combo但不打断B2B
+        assert_eq!(attack.on_lock(2, true), 5);
+        assert_eq!(attack.on_lock(1, false), 0);
+        assert!(!attack.b2b);
+    }
+
+    #[test]
+    fn attack_never_exceeds_max() {
+        let mut attack = AttackState::default();
+        let most = (0..20).map(|_| attack.on_lock(3, true)).max();
+        assert_eq!(most, Some(ATTACK_MAX));
+    }
+
+    #[test]
+    fn attack_cancels_pending_garbage_first() {
+        let mut queue = GarbageQueue::default();
+        queue.pending.extend([3, 2]);
+        assert_eq!(queue.cancel(4), 0);
+        assert_eq!(queue.pending, [1]);
+        assert_eq!(queue.cancel(5), 4);
+        assert_eq!(queue.total(), 0);
+    }
+
+    #[test]
+    fn garbage_is_inserted_when_no_line_is_cleared() {
+        let mut engine = engine_with(I, Vec::new());
+        assert_eq!(engine.apply(Input::Garbage(2)), None);
+        assert_eq!(engine.apply(Input::Garbage(0)), None);
+        assert_eq!(engine.pending_garbage(), 2);
+        let lock = hard_drop(&mut engine);
+        assert_eq!((lock.lines, lock.attack), (0, 0));
+        assert_eq!(engine.pending_garbage(), 0);
+        //同一次攻击的两行共用一个缺口，落定的方块被顶到垃圾行之上
+        let board = engine.board();
+        assert_eq!(board.len(), 6);
+        assert_eq!(board[0], board[1]);
+        assert_eq!(board[0].iter().filter(|&&c| c == Cell::Empty).count(), 1);
+        assert!(board[0].iter().all(|&c| c != Cell::Block));
+        assert!((2..6).all(|row| board[row][6] == Cell::Block));
+    }
+
+    #[test]
+    fn pending_garbage_is_capped() {
+        let mut engine = engine_with(I, Vec::new());
+        engine.apply(Input::Garbage(u32::MAX));
+        assert_eq!(engine.pending_garbage(), GARBAGE_MAX);
+        engine.apply(Input::Garbage(u32::MAX));
+        assert_eq!(engine.pending_garbage(), GARBAGE_MAX);
+    }
+
+    #[test]
+    fn rotation_turns_around_the_center() {
+        let mut engine = engine_with(I, Vec::new());
+        let spawn = sorted(engine.piece());
+        assert_eq!(engine.apply(Input::Rotate), None);
+        assert_eq!(
+            sorted(engine.piece()),
+            vec![(4, 18), (5, 18), (6, 18), (7, 18)]
+        );
+        for _ in 0..3 {
+            engine.apply(Input::Rotate);
+        }
+        assert_eq!(sorted(engine.piece()), spawn);
+    }
+
+    #[test]
+    fn blocked_rotation_keeps_the_piece() {
+        let mut board = vec![[Cell::Empty; COLS]; 19];
+        board[18][4] = Cell::Block;
+        let mut engine = engine_with(I, board);
+        let spawn = *engine.piece();
+        assert_eq!(engine.apply(Input::Rotate), None);
+        assert_eq!(*engine.piece(), spawn);
+        assert!(!engine.rotated);
+    }
+}
@@ -0,0 +1,173 @@
+//按键精简度(finesse)：把每个方块从出场到落定所需的最少按键与玩家实际的按键比较，多按即记一次失误。
+//只统计左右移与旋转，软降与重力不计
+use crate::engine::{Engine, Input, Lock};
+use std::collections::HashSet;
+
+pub fn is_key(input: Input) -> bool {
+    matches!(input, Input::Left | Input::Right | Input::Rotate)
+}
+
+//从当前局面把方块落定到target(已排序)所需的最少按键；逐层搜索，每层先用不计数的软降扩展
+pub fn minimal_keys(engine: &Engine, target: &[(i32, i32); 4]) -> Option<Vec<Input>> {
+    let reached = |lock: Lock| {
+        let mut cells = lock.cells;
+        cells.sort_unstable();
+        cells == *target
+    };
+    let mut seen = HashSet::new();
+    seen.insert(*engine.piece());
+    let mut frontier = vec![(engine.clone(), Vec::new())];
+    while !frontier.is_empty() {
+        let mut i = 0;
+        while i < frontier.len() {
+            let (mut next, keys) = frontier[i].clone();
+            i += 1;
+            match next.apply(Input::SoftDrop) {
+                Some(lock) if reached(lock) => return Some(keys),
+                Some(_) => {}
+                None => {
+                    if seen.insert(*next.piece()) {
+                        frontier.push((next, keys));
+                    }
+                }
+            }
+        }
+        let mut deeper = Vec::new();
+        for (state, keys) in frontier {
+            for input in [Input::Left, Input::Right, Input::Rotate] {
+                let mut next = state.clone();
+                let mut keys = keys.clone();
+                keys.push(input);
+                match next.apply(input) {
+                    Some(lock) if reached(lock) => return Some(keys),
+                    Some(_) => {}
+                    None => {
+                        if next.piece() != state.piece() && seen.insert(*next.piece()) {
+                            deeper.push((next, keys));
+                        }
+                    }
+                }
+            }
+        }
+        frontier = deeper;
+    }
+    None
+}
+
+//逐块统计：记下方块出场时的局面与之后的按键数，落定时与最少按键比较
+#[derive(Clone)]
+pub struct Finesse {
+    start: Engine,
+    keys: u32,
+    pub pieces: u32,
+    pub faults: u32,
+    //超出最少按键的总次数
+    pub extra: u32,
+}
+
+impl Finesse {
+    pub fn new(engine: &Engine) -> Self {
+        Finesse {
+            start: engine.clone(),
+            keys: 0,
+            pieces: 0,
+            faults: 0,
+            extra: 0,
+        }
+    }
+
+    //当前方块出场时的局面
+    pub fn start(&self) -> &Engine {
+        &self.start
+    }
+
+    //记录一条已执行的输入，lock为其落定结果，engine为执行后的局面
+    pub fn record(&mut self, input: Input, lock: Option<&Lock>, engine: &Engine) {
+        if is_key(input) {
+            self.keys += 1;
+        }
+        let lock = match lock {
+            Some(lock) => lock,
+            None => return,
+        };
+        let mut cells = lock.cells;
+        cells.sort_unstable();
+        if let Some(minimal) = minimal_keys(&self.start, &cells) {
+            let extra = self.keys.saturating_sub(minimal.len() as u32);
+            if extra > 0 {
+                self.faults += 1;
+                self.extra += extra;
+            }
+        }
+        self.pieces += 1;
+        self.start = engine.clone();
+        self.keys = 0;
+    }
+
+    //没有多按的方块所占比例
+    pub fn accuracy(&self) -> f64 {
+        if self.pieces == 0 {
+            return 1.0;
+        }
+        1.0 - self.faults as f64 / self.pieces as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //按keys操作后软降到底
+    fn play(engine: &mut Engine, keys: &[Input]) -> Lock {
+        for &key in keys {
+            assert_eq!(engine.apply(key), None);
+        }
+        loop {
+            if let Some(lock) = engine.apply(Input::SoftDrop) {
+                return lock;
+            }
+        }
+    }
+
+    fn target(engine: &Engine, keys: &[Input]) -> [(i32, i32); 4] {
+        let mut cells = play(&mut engine.clone(), keys).cells;
+        cells.sort_unstable();
+        cells
+    }
+
+    #[test]
+    fn straight_drop_needs_no_keys() {
+        let engine = Engine::new(1);
+        assert_eq!(minimal_keys(&engine, &target(&engine, &[])), Some(vec![]));
+    }
+
+    #[test]
+    fn minimal_keys_reach_the_target() {
+        let engine = Engine::new(2);
+        let played = [Input::Left, Input::Right, Input::Left, Input::Left];
+        let cells = target(&engine, &played);
+        let keys = minimal_keys(&engine, &cells).unwrap();
+        assert!(!keys.is_empty() && keys.len() <= 2, "{:?}", keys);
+        assert_eq!(target(&engine, &keys), cells);
+        //棋盘外的格子搜不到
+        assert_eq!(minimal_keys(&engine, &[(100, 0); 4]), None);
+    }
+
+    #[test]
+    fn extra_keys_count_as_a_fault() {
+        let mut engine = Engine::new(3);
+        let mut finesse = Finesse::new(&engine);
+        for input in [Input::Left, Input::Right, Input::Left, Input::Left] {
+            let lock = engine.apply(input);
+            finesse.record(input, lock.as_ref(), &engine);
+        }
+        let mut lock = None;
+        while lock.is_none() {
+            lock = engine.apply(Input::SoftDrop);
+            finesse.record(Input::SoftDrop, lock.as_ref(), &engine);
+        }
+        assert_eq!((finesse.pieces, finesse.faults), (1, 1));
+        assert!(finesse.extra >= 2);
+        assert_eq!(finesse.accuracy(), 0.0);
+    }
+}
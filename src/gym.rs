@@ -0,0 +1,270 @@
+//强化学习环境：reset(seed)开局，step(action)推进一步并返回(观测, 奖励, 是否结束)，不需要窗口。
+//动作为离散编号：按键级对应KEYS，落点级对应当前方块的合法落点列表
+use crate::bot::{self, Placement};
+use crate::engine::{Cell, Engine, Input, COLS, ROWS};
+use rayon::prelude::*;
+
+//按键级动作，None为本步不操作
+pub const KEYS: [Option<Input>; 5] = [
+    None,
+    Some(Input::Left),
+    Some(Input::Right),
+    Some(Input::SoftDrop),
+    Some(Input::Rotate),
+];
+//按键级每隔多少步施加一次重力，与客户端每秒16次键盘采样、1次重力一致
+const GRAVITY_STEPS: u32 = 16;
+//批量推进时每个线程至少分到的环境数，按键级单步很轻，分得太碎反而慢
+const PAR_CHUNK: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActionSpace {
+    Keypress,
+    Placement,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Observation {
+    //ROWS*COLS个格子，自底向上逐行，1为已落定
+    pub board: Vec<u8>,
+    //当前方块各格的(列,行)
+    pub piece: [(i32, i32); 4],
+    //next区方块相对其中心的偏移(半格)
+    pub next: [(i32, i32); 4],
+    pub pending_garbage: u32,
+    pub score: u32,
+    //当前可选的动作数
+    pub actions: usize,
+}
+
+pub type StepResult = (Observation, f64, bool);
+
+pub struct Env {
+    space: ActionSpace,
+    engine: Engine,
+    placements: Vec<Placement>,
+    steps: u32,
+}
+
+impl Env {
+    pub fn new(space: ActionSpace) -> Self {
+        let mut env = Env {
+            space,
+            engine: Engine::new(0),
+            placements: Vec::new(),
+            steps: 0,
+        };
+        env.reset(0);
+        env
+    }
+
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.engine = Engine::new(seed);
+        self.steps = 0;
+        self.refresh();
+        self.observe()
+    }
+
+    //奖励为本步得分的增量；动作编号须小于观测中的actions，否则不推进并返回错误
+    pub fn step(&mut self, action: usize) -> Result<StepResult, String> {
+        self.check(action)?;
+        let before = self.engine.score();
+        match self.space {
+            ActionSpace::Keypress => {
+                self.steps += 1;
+                let mut locked = match KEYS[action] {
+                    Some(input) => self.engine.apply(input).is_some(),
+                    None => false,
+                };
+                if !locked && self.steps.is_multiple_of(GRAVITY_STEPS) {
+                    locked = self.engine.apply(Input::Gravity).is_some();
+                }
+                if locked {
+                    self.steps = 0;
+                }
+            }
+            ActionSpace::Placement => {
+                for &input in self.placements[action].inputs.iter() {
+                    self.engine.apply(input);
+                }
+                self.refresh();
+            }
+        }
+        let reward = (self.engine.score() - before) as f64;
+        Ok((self.observe(), reward, self.engine.topped_out()))
+    }
+
+    fn check(&self, action: usize) -> Result<(), String> {
+        match self.action_count() {
+            count if action < count => Ok(()),
+            count => Err(format!("action {} out of range 0..{}", action, count)),
+        }
+    }
+
+    pub fn action_count(&self) -> usize {
+        match self.space {
+            _ if self.engine.topped_out() => 0,
+            ActionSpace::Keypress => KEYS.len(),
+            ActionSpace::Placement => self.placements.len(),
+        }
+    }
+
+    //落点级动作对应的操作序列与启发式评分
+    pub fn placements(&self) -> &[Placement] {
+        &self.placements
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    fn refresh(&mut self) {
+        if self.space == ActionSpace::Placement {
+            self.placements = bot::placements(&self.engine);
+        }
+    }
+
+    fn observe(&self) -> Observation {
+        let mut board = vec![0; ROWS * COLS];
+        for (row, cells) in self.engine.board().iter().enumerate().take(ROWS) {
+            for (col, cell) in cells.iter().enumerate() {
+                board[row * COLS + col] = (*cell != Cell::Empty) as u8;
+            }
+        }
+        Observation {
+            board,
+            piece: *self.engine.piece(),
+            next: *self.engine.next_shape(),
+            pending_garbage: self.engine.pending_garbage(),
+            score: self.engine.score(),
+            actions: self.action_count(),
+        }
+    }
+}
+
+//批量环境：一次在线程池上并行推进所有环境，结束的环境用下一个种子自动重开
+pub struct VecEnv {
+    envs: Vec<Env>,
+    seeds: Vec<u64>,
+}
+
+impl VecEnv {
+    pub fn new(count: usize, space: ActionSpace) -> Self {
+        VecEnv {
+            envs: (0..count).map(|_| Env::new(space)).collect(),
+            seeds: vec![0; count],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.envs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.envs.is_empty()
+    }
+
+    //第i个环境以seed+i开局，之后每次重开种子加上环境数
+    pub fn reset(&mut self, seed: u64) -> Vec<Observation> {
+        let count = self.envs.len() as u64;
+        self.envs
+            .iter_mut()
+            .zip(self.seeds.iter_mut())
+            .enumerate()
+            .map(|(i, (env, env_seed))| {
+                *env_seed = seed.wrapping_add(i as u64);
+                let observation = env.reset(*env_seed);
+                *env_seed = env_seed.wrapping_add(count);
+                observation
+            })
+            .collect()
+    }
+
+    //结束的环境返回重开后的观测，done仍为true。先检查全部动作，有不合法的则都不推进
+    pub fn step(&mut self, actions: &[usize]) -> Result<Vec<StepResult>, String> {
+        if actions.len() != self.envs.len() {
+            return Err(format!(
+                "expected {} actions, got {}",
+                self.envs.len(),
+                actions.len()
+            ));
+        }
+        for (i, (env, &action)) in self.envs.iter().zip(actions).enumerate() {
+            env.check(action).map_err(|e| format!("env {}: {}", i, e))?;
+        }
+        let count = self.envs.len() as u64;
+        Ok(self
+            .envs
+            .par_iter_mut()
+            .zip(self.seeds.par_iter_mut())
+            .zip(actions.par_iter())
+            .with_min_len(PAR_CHUNK)
+            .map(|((env, seed), &action)| {
+                let (observation, reward, done) = env.step(action).unwrap();
+                if !done {
+                    return (observation, reward, done);
+                }
+                let observation = env.reset(*seed);
+                *seed = seed.wrapping_add(count);
+                (observation, reward, done)
+            })
+            .collect())
+    }
+
+    pub fn envs(&self) -> &[Env] {
+        &self.envs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOFT_DROP: usize = 3;
+
+    #[test]
+    fn out_of_range_action_is_an_error() {
+        let mut env = Env::new(ActionSpace::Keypress);
+        let before = env.observe();
+        assert!(env.step(KEYS.len()).is_err());
+        assert_eq!(env.observe(), before);
+        let mut envs = VecEnv::new(2, ActionSpace::Keypress);
+        assert!(envs.step(&[0]).is_err());
+        assert!(envs.step(&[0, KEYS.len()]).is_err());
+    }
+
+    #[test]
+    fn same_seed_same_trajectory() {
+        let run = || {
+            let mut envs = VecEnv::new(8, ActionSpace::Placement);
+            let mut observations = envs.reset(42);
+            let mut trace = Vec::new();
+            for step in 0..100 {
+                let actions: Vec<usize> = observations.iter().map(|o| step % o.actions).collect();
+                let results = envs.step(&actions).unwrap();
+                observations = results.iter().map(|r| r.0.clone()).collect();
+                trace.push(results);
+            }
+            trace
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn finished_env_restarts_with_next_seed() {
+        let mut envs = VecEnv::new(2, ActionSpace::Keypress);
+        envs.reset(7);
+        //只下落不移动，很快堆到顶
+        for _ in 0..100_000 {
+            let results = envs.step(&[SOFT_DROP, SOFT_DROP]).unwrap();
+            if let Some(i) = results.iter().position(|r| r.2) {
+                //第i个环境以7+i开局，重开时种子加上环境数
+                let fresh = Env::new(ActionSpace::Keypress).reset(7 + i as u64 + 2);
+                assert_eq!(results[i].0, fresh);
+                assert!(!envs.envs()[i].engine().topped_out());
+                return;
+            }
+        }
+        panic!("never topped out");
+    }
+}
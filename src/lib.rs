@@ -0,0 +1,14 @@
+//客户端与服务端共用的部分：生成的协议代码、无渲染的规则引擎、按键精简度统计、内置AI、强化学习环境、排行榜名次统计与分榜的模式名
+pub mod bot;
+pub mod engine;
+pub mod finesse;
+pub mod gym;
+pub mod modes;
+pub mod ranking;
+
+//玩家名字的长度上限(字符数)，客户端输入与服务端注册共用
+pub const NAME_MAX: usize = 16;
+
+pub mod rblock {
+    tonic::include_proto!("rblock");
+}
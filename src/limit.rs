@@ -0,0 +1,82 @@
+//按客户端IP限速：每个IP一个令牌桶，每次调用消耗一个令牌，令牌按固定速率补充
+use crate::config::RateLimit;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tonic::{Request, Status};
+
+//记录的IP超过该数量时清理已补满的桶
+const PRUNE_THRESHOLD: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: &RateLimit) -> Self {
+        RateLimiter {
+            rate: limit.requests_per_second as f64,
+            burst: limit.burst as f64,
+            buckets: Arc::default(),
+        }
+    }
+
+    //不限速或取不到对端地址时放行
+    #[allow(clippy::result_large_err)]
+    pub fn check<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        let ip = match request.remote_addr() {
+            Some(addr) if self.rate > 0.0 => addr.ip(),
+            _ => return Ok(()),
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > PRUNE_THRESHOLD {
+            let (rate, burst) = (self.rate, self.burst);
+            buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < burst
+            });
+        }
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return Err(Status::resource_exhausted("rate limit exceeded"));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    //先限速再交给inner，用于与其他拦截器组合
+    #[allow(clippy::result_large_err)]
+    pub fn wrap<F>(
+        &self,
+        mut inner: F,
+    ) -> impl FnMut(Request<()>) -> Result<Request<()>, Status> + Clone
+    where
+        F: FnMut(Request<()>) -> Result<Request<()>, Status> + Clone,
+    {
+        let limiter = self.clone();
+        move |request: Request<()>| {
+            limiter.check(&request)?;
+            inner(request)
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn interceptor(&self) -> impl FnMut(Request<()>) -> Result<Request<()>, Status> + Clone {
+        self.wrap(Ok)
+    }
+}
@@ -0,0 +1,96 @@
+//大厅：列出、创建与加入房间，入座后凭座位密钥离开或准备，全员准备后倒计时开局
+use crate::auth;
+use crate::royale::{schedule_start, Room, RoomError, SharedRooms, ROYALE_CAPACITY};
+use russia_block::rblock::lobby_server::Lobby;
+use russia_block::rblock::{
+    CreateRoomRequest, JoinRoomRequest, JoinRoomResponse, LeaveRoomRequest, ListRoomsRequest,
+    ListRoomsResponse, RoomInfo, SetReadyRequest,
+};
+use tonic::{Request, Response, Status};
+
+#[derive(Default, Debug)]
+pub struct LobbyService {
+    pub rooms: SharedRooms,
+}
+
+#[tonic::async_trait]
+impl Lobby for LobbyService {
+    async fn create_room(
+        &self,
+        request: Request<CreateRoomRequest>,
+    ) -> Result<Response<JoinRoomResponse>, Status> {
+        let caller = auth::caller(&request);
+        let req = request.into_inner();
+        let capacity = match req.capacity as usize {
+            0 => ROYALE_CAPACITY,
+            capacity => capacity.clamp(2, ROYALE_CAPACITY),
+        };
+        let mut rooms = self.rooms.lock().unwrap();
+        let room_id = rooms.create(req.room_name, capacity, false).id;
+        //创建者自动加入房间
+        let (player_id, room) = rooms.add_player(room_id, caller)?;
+        Ok(Response::new(JoinRoomResponse {
+            player_id,
+            room: Some(room.info()),
+            seat_secret: room.player(player_id).unwrap().secret.clone(),
+        }))
+    }
+
+    async fn list_rooms(
+        &self,
+        _request: Request<ListRoomsRequest>,
+    ) -> Result<Response<ListRoomsResponse>, Status> {
+        let rooms = self.rooms.lock().unwrap();
+        Ok(Response::new(ListRoomsResponse {
+            rooms: rooms.rooms.iter().map(Room::info).collect(),
+        }))
+    }
+
+    async fn join_room(
+        &self,
+        request: Request<JoinRoomRequest>,
+    ) -> Result<Response<JoinRoomResponse>, Status> {
+        let caller = auth::caller(&request);
+        let req = request.into_inner();
+        let mut rooms = self.rooms.lock().unwrap();
+        let (player_id, room) = rooms.add_player(req.room_id, caller)?;
+        Ok(Response::new(JoinRoomResponse {
+            player_id,
+            room: Some(room.info()),
+            seat_secret: room.player(player_id).unwrap().secret.clone(),
+        }))
+    }
+
+    async fn leave_room(
+        &self,
+        request: Request<LeaveRoomRequest>,
+    ) -> Result<Response<RoomInfo>, Status> {
+        let req = request.into_inner();
+        let mut rooms = self.rooms.lock().unwrap();
+        if let Some(room) = rooms.room(req.room_id) {
+            if !room.owns_seat(req.player_id, &req.seat_secret) {
+                return Err(Status::permission_denied("not a member of this room"));
+            }
+        }
+        rooms.leave(req.room_id, req.player_id);
+        let info = rooms.room(req.room_id).map(|room| room.info());
+        Ok(Response::new(info.unwrap_or_default()))
+    }
+
+    async fn set_ready(
+        &self,
+        request: Request<SetReadyRequest>,
+    ) -> Result<Response<RoomInfo>, Status> {
+        let req = request.into_inner();
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.room(req.room_id).ok_or(RoomError::NotFound)?;
+        if !room.owns_seat(req.player_id, &req.seat_secret) {
+            return Err(Status::permission_denied("not a member of this room"));
+        }
+        room.player(req.player_id).unwrap().ready = req.ready;
+        if let Some(deadline) = room.update_countdown() {
+            schedule_start(self.rooms.clone(), req.room_id, deadline);
+        }
+        Ok(Response::new(room.info()))
+    }
+}
@@ -0,0 +1,4 @@
+//按模式分榜时用到的模式名，客户端与服务端共用
+
+//单独设榜的模式，其他模式的成绩只计入全部模式的榜
+pub const LEADERBOARD_MODES: [&str; 5] = ["single", "training", "bot", "versus", "royale"];
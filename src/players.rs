@@ -0,0 +1,208 @@
+//玩家注册与登录：分配不变的player_id并登记显示名，排行榜按player_id显示玩家当前的名字。
+//另按玩家统计历史成绩
+use crate::auth::{self, TokenKey};
+use crate::rblock::players_server::Players;
+use crate::rblock::{
+    Credential, GameRecord, LoginRequest, ModeStats, PlayerProfile, PlayerStats,
+    PlayerStatsRequest, RegisterRequest, ScoreEntry, Session,
+};
+use crate::storage::{self, SharedStore, Store};
+use russia_block::NAME_MAX;
+use std::collections::BTreeMap;
+use tonic::{Request, Response, Status};
+
+//不带令牌的调用者一律显示为匿名，不能注册为玩家名
+pub const ANONYMOUS: &str = "anonymous";
+//未指定history_limit时返回的局数与允许的上限
+const HISTORY_DEFAULT: u32 = 100;
+const HISTORY_MAX: u32 = 1000;
+
+//去掉首尾空白，长度为1到NAME_MAX个字符且不含控制字符
+pub fn normalize_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > NAME_MAX {
+        return Err(format!("name must be 1 to {} characters", NAME_MAX));
+    }
+    if name.chars().any(char::is_control) {
+        return Err("name contains control characters".to_string());
+    }
+    if name.eq_ignore_ascii_case(ANONYMOUS) {
+        return Err(format!("name {} is reserved", ANONYMOUS));
+    }
+    Ok(name.to_string())
+}
+
+//调用者登记的显示名，匿名时为ANONYMOUS
+#[allow(clippy::result_large_err)]
+pub fn display_name(store: &Store, caller: Option<&str>) -> Result<String, Status> {
+    match caller {
+        Some(player_id) => store
+            .profile(player_id)
+            .map(|profile| profile.name.clone())
+            .ok_or_else(|| Status::not_found("unknown player_id")),
+        None => Ok(ANONYMOUS.to_string()),
+    }
+}
+
+pub struct PlayerService {
+    pub store: SharedStore,
+    pub key: TokenKey,
+}
+
+impl PlayerService {
+    fn session(&self, profile: PlayerProfile, secret: String) -> Session {
+        let (token, expires_at) = self.key.issue(&profile.player_id);
+        Session {
+            token,
+            expires_at,
+            profile: Some(profile),
+            secret,
+        }
+    }
+}
+
+fn pps(pieces: u32, duration_ms: u32) -> f32 {
+    match duration_ms {
+        0 => 0.0,
+        ms => pieces as f32 * 1000.0 / ms as f32,
+    }
+}
+
+fn game_record(entry: &ScoreEntry) -> GameRecord {
+    GameRecord {
+        mode: entry.mode.clone(),
+        score: entry.score,
+        lines: entry.lines,
+        pieces: entry.pieces,
+        duration_ms: entry.duration_ms,
+        pps: pps(entry.pieces, entry.duration_ms),
+        played_at: storage::entry_time(entry),
+    }
+}
+
+//汇总games，最佳分数同分时取最早的一局
+fn mode_stats(mode: &str, games: &[GameRecord]) -> ModeStats {
+    let mut stats = ModeStats {
+        mode: mode.to_string(),
+        games: games.len() as u32,
+        ..Default::default()
+    };
+    for (i, game) in games.iter().enumerate() {
+        if i == 0 || game.score > stats.best_score {
+            stats.best_score = game.score;
+            stats.best_score_at = game.played_at;
+        }
+        stats.best_lines = stats.best_lines.max(game.lines);
+        stats.best_pps = stats.best_pps.max(game.pps);
+        stats.total_score += game.score as u64;
+        stats.total_lines += game.lines as u64;
+        stats.total_duration_ms += game.duration_ms as u64;
+    }
+    if !games.is_empty() {
+        stats.average_score = stats.total_score as f32 / games.len() as f32;
+    }
+    stats
+}
+
+#[tonic::async_trait]
+impl Players for PlayerService {
+    async fn register(
+        &self,
+        request: Request<RegisterRequest>,
+    ) -> Result<Response<Session>, Status> {
+        let caller = auth::caller(&request);
+        let req = request.into_inner();
+        let name = normalize_name(&req.name).map_err(Status::invalid_argument)?;
+        let mut store = self.store.lock().unwrap();
+        if !req.player_id.is_empty() {
+            //改名只能改自己的
+            if caller.as_deref() != Some(req.player_id.as_str()) {
+                return Err(Status::permission_denied(
+                    "session does not match player_id",
+                ));
+            }
+            let old = store
+                .profile(&req.player_id)
+                .ok_or_else(|| Status::not_found("unknown player_id"))?;
+            let profile = PlayerProfile {
+                name,
+                ..old.clone()
+            };
+            store.save_profile(profile.clone());
+            return Ok(Response::new(self.session(profile, String::new())));
+        }
+        let mut player_id = format!("{:016x}", rand::random::<u64>());
+        while store.profile(&player_id).is_some() {
+            player_id = format!("{:016x}", rand::random::<u64>());
+        }
+        let secret = auth::new_secret();
+        let profile = PlayerProfile {
+            player_id: player_id.clone(),
+            name,
+            registered_at: storage::unix_now(),
+        };
+        store.save_profile(profile.clone());
+        store.save_credential(Credential {
+            player_id,
+            secret_sha256: auth::hash_secret(&secret),
+        });
+        Ok(Response::new(self.session(profile, secret)))
+    }
+
+    async fn login(&self, request: Request<LoginRequest>) -> Result<Response<Session>, Status> {
+        let req = request.into_inner();
+        let store = self.store.lock().unwrap();
+        let valid = store
+            .credential(&req.player_id)
+            .is_some_and(|c| c.secret_sha256 == auth::hash_secret(&req.secret));
+        if !valid {
+            return Err(Status::unauthenticated("wrong player_id or secret"));
+        }
+        let profile = store.profile(&req.player_id).unwrap().clone();
+        Ok(Response::new(self.session(profile, String::new())))
+    }
+
+    async fn get_player_stats(
+        &self,
+        request: Request<PlayerStatsRequest>,
+    ) -> Result<Response<PlayerStats>, Status> {
+        let caller = auth::caller(&request);
+        let req = request.into_inner();
+        let player_id = match (req.player_id.is_empty(), caller) {
+            (false, _) => req.player_id,
+            (true, Some(caller)) => caller,
+            (true, None) => return Err(Status::unauthenticated("login required")),
+        };
+        let limit = match req.history_limit {
+            0 => HISTORY_DEFAULT,
+            limit => limit.min(HISTORY_MAX),
+        } as usize;
+        let store = self.store.lock().unwrap();
+        let profile = store
+            .profile(&player_id)
+            .ok_or_else(|| Status::not_found("unknown player_id"))?
+            .clone();
+        let games: Vec<GameRecord> = store
+            .history(&player_id)
+            .into_iter()
+            .filter(|e| req.mode.is_empty() || e.mode == req.mode)
+            .map(game_record)
+            .collect();
+        let mut by_mode: BTreeMap<&str, Vec<GameRecord>> = BTreeMap::new();
+        for game in &games {
+            by_mode.entry(&game.mode).or_default().push(game.clone());
+        }
+        let modes = by_mode
+            .iter()
+            .map(|(mode, games)| mode_stats(mode, games))
+            .collect();
+        let stats = PlayerStats {
+            profile: Some(profile),
+            overall: Some(mode_stats(&req.mode, &games)),
+            modes,
+            total_games: games.len() as u32,
+            history: games[games.len().saturating_sub(limit)..].to_vec(),
+        };
+        Ok(Response::new(stats))
+    }
+}
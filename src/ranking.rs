@@ -0,0 +1,192 @@
+//排行榜的顺序统计树：按分数从高到低排列的treap，节点记录子树大小，
+//插入、求名次与按名次取值均为期望O(log n)。同分者先插入的排在前面
+use rand::prng::XorShiftRng;
+use rand::{Rng, SeedableRng};
+
+const NIL: usize = usize::MAX;
+
+struct Node<T> {
+    score: u32,
+    priority: u32,
+    size: usize,
+    left: usize,
+    right: usize,
+    value: T,
+}
+
+pub struct RankTree<T> {
+    nodes: Vec<Node<T>>,
+    root: usize,
+    rng: XorShiftRng,
+}
+
+impl<T> RankTree<T> {
+    pub fn new() -> Self {
+        RankTree {
+            nodes: Vec::new(),
+            root: NIL,
+            rng: XorShiftRng::seed_from_u64(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.size(self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root == NIL
+    }
+
+    pub fn insert(&mut self, score: u32, value: T) {
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            score,
+            priority: self.rng.gen(),
+            size: 1,
+            left: NIL,
+            right: NIL,
+            value,
+        });
+        let (higher, lower) = self.split(self.root, score);
+        let left = self.merge(higher, node);
+        self.root = self.merge(left, lower);
+    }
+
+    //严格高于score的条目数
+    pub fn rank(&self, score: u32) -> usize {
+        let mut count = 0;
+        let mut t = self.root;
+        while t != NIL {
+            let node = &self.nodes[t];
+            if node.score > score {
+                count += self.size(node.left) + 1;
+                t = node.right;
+            } else {
+                t = node.left;
+            }
+        }
+        count
+    }
+
+    //第index名(从0开始)的分数与条目
+    pub fn get(&self, mut index: usize) -> Option<(u32, &T)> {
+        let mut t = self.root;
+        while t != NIL {
+            let node = &self.nodes[t];
+            let left = self.size(node.left);
+            if index < left {
+                t = node.left;
+            } else if index == left {
+                return Some((node.score, &node.value));
+            } else {
+                index -= left + 1;
+                t = node.right;
+            }
+        }
+        None
+    }
+
+    //从第offset名起最多limit条
+    pub fn range(&self, offset: usize, limit: usize) -> impl Iterator<Item = (u32, &T)> {
+        let end = self.len().min(offset.saturating_add(limit));
+        (offset..end).filter_map(move |i| self.get(i))
+    }
+
+    fn size(&self, t: usize) -> usize {
+        if t == NIL {
+            0
+        } else {
+            self.nodes[t].size
+        }
+    }
+
+    fn update(&mut self, t: usize) {
+        let size = self.size(self.nodes[t].left) + self.size(self.nodes[t].right) + 1;
+        self.nodes[t].size = size;
+    }
+
+    //拆成(分数不低于score的部分, 分数低于score的部分)
+    fn split(&mut self, t: usize, score: u32) -> (usize, usize) {
+        if t == NIL {
+            return (NIL, NIL);
+        }
+        if self.nodes[t].score >= score {
+            let (left, right) = self.split(self.nodes[t].right, score);
+            self.nodes[t].right = left;
+            self.update(t);
+            (t, right)
+        } else {
+            let (left, right) = self.split(self.nodes[t].left, score);
+            self.nodes[t].left = right;
+            self.update(t);
+            (left, t)
+        }
+    }
+
+    //a中所有条目排在b之前
+    fn merge(&mut self, a: usize, b: usize) -> usize {
+        if a == NIL {
+            return b;
+        }
+        if b == NIL {
+            return a;
+        }
+        if self.nodes[a].priority > self.nodes[b].priority {
+            let right = self.merge(self.nodes[a].right, b);
+            self.nodes[a].right = right;
+            self.update(a);
+            a
+        } else {
+            let left = self.merge(a, self.nodes[b].left);
+            self.nodes[b].left = left;
+            self.update(b);
+            b
+        }
+    }
+}
+
+impl<T> Default for RankTree<T> {
+    fn default() -> Self {
+        RankTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //与按分数从高到低稳定排序的Vec对照，同分者保持插入顺序
+    #[test]
+    fn matches_a_sorted_vec() {
+        let mut rng = XorShiftRng::seed_from_u64(7);
+        let mut tree = RankTree::new();
+        let mut expected: Vec<(u32, usize)> = Vec::new();
+        for i in 0..2000 {
+            let score = rng.gen_range(0, 100);
+            tree.insert(score, i);
+            expected.push((score, i));
+        }
+        expected.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        assert_eq!(tree.len(), expected.len());
+        for (index, &(score, value)) in expected.iter().enumerate() {
+            assert_eq!(tree.get(index), Some((score, &value)));
+        }
+        assert_eq!(tree.get(expected.len()), None);
+        for score in 0..=100 {
+            let higher = expected.iter().filter(|&&(s, _)| s > score).count();
+            assert_eq!(tree.rank(score), higher);
+        }
+    }
+
+    #[test]
+    fn range_is_clamped_to_the_tree() {
+        let mut tree = RankTree::new();
+        assert!(tree.is_empty());
+        for score in [5, 9, 1] {
+            tree.insert(score, ());
+        }
+        let scores: Vec<u32> = tree.range(1, 10).map(|(score, _)| score).collect();
+        assert_eq!(scores, [5, 1]);
+        assert_eq!(tree.range(3, usize::MAX).count(), 0);
+    }
+}
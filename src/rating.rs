@@ -0,0 +1,154 @@
+//Glicko-2评分，对战中每局视为一个评分周期
+//参考 Mark E. Glickman, "Example of the Glicko-2 system"
+use std::f64::consts::PI;
+
+//系统常数，约束波动率随时间的变化
+const TAU: f64 = 0.5;
+//Glicko与Glicko-2刻度之间的换算系数
+const SCALE: f64 = 173.7178;
+const EPSILON: f64 = 0.000001;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            rating: 1500.0,
+            deviation: 350.0,
+            volatility: 0.06,
+        }
+    }
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+//player与opponent对战一局后player的新评分，score为1胜0负
+pub fn update(player: Rating, opponent: Rating, score: f64) -> Rating {
+    update_period(player, &[(opponent, score)])
+}
+
+//一个评分周期内与各对手的(对手, 得分)，没有对局时只增大评分偏差
+pub fn update_period(player: Rating, games: &[(Rating, f64)]) -> Rating {
+    let mu = (player.rating - 1500.0) / SCALE;
+    let phi = player.deviation / SCALE;
+    if games.is_empty() {
+        let phi_star = (phi * phi + player.volatility * player.volatility).sqrt();
+        return Rating {
+            deviation: SCALE * phi_star,
+            ..player
+        };
+    }
+
+    //每局的(g, 期望得分, 实际得分)
+    let results: Vec<(f64, f64, f64)> = games
+        .iter()
+        .map(|(opponent, score)| {
+            let mu_j = (opponent.rating - 1500.0) / SCALE;
+            let g_j = g(opponent.deviation / SCALE);
+            let expected = 1.0 / (1.0 + (-g_j * (mu - mu_j)).exp());
+            (g_j, expected, *score)
+        })
+        .collect();
+    let v = 1.0
+        / results
+            .iter()
+            .map(|(g_j, e, _)| g_j * g_j * e * (1.0 - e))
+            .sum::<f64>();
+    let improvement: f64 = results.iter().map(|(g_j, e, s)| g_j * (s - e)).sum();
+    let delta = v * improvement;
+
+    //用Illinois算法求解新的波动率
+    let a = (player.volatility * player.volatility).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        let d = phi * phi + v + ex;
+        ex * (delta * delta - d) / (2.0 * d * d) - (x - a) / (TAU * TAU)
+    };
+    let mut big_a = a;
+    let mut big_b = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * TAU) < 0.0 {
+            k += 1.0;
+        }
+        a - k * TAU
+    };
+    let mut f_a = f(big_a);
+    let mut f_b = f(big_b);
+    while (big_b - big_a).abs() > EPSILON {
+        let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(big_c);
+        if f_c * f_b <= 0.0 {
+            big_a = big_b;
+            f_a = f_b;
+        } else {
+            f_a /= 2.0;
+        }
+        big_b = big_c;
+        f_b = f_c;
+    }
+    let volatility = (big_a / 2.0).exp();
+
+    let phi_star = (phi * phi + volatility * volatility).sqrt();
+    let phi_new = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+    let mu_new = mu + phi_new * phi_new * improvement;
+    Rating {
+        rating: SCALE * mu_new + 1500.0,
+        deviation: SCALE * phi_new,
+        volatility,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(rating: f64, deviation: f64) -> Rating {
+        Rating {
+            rating,
+            deviation,
+            ..Default::default()
+        }
+    }
+
+    //论文中的算例：1500/200/0.06的玩家胜1400/30，负1550/100与1700/300
+    #[test]
+    fn matches_worked_example() {
+        let games = [
+            (rating(1400.0, 30.0), 1.0),
+            (rating(1550.0, 100.0), 0.0),
+            (rating(1700.0, 300.0), 0.0),
+        ];
+        let new = update_period(rating(1500.0, 200.0), &games);
+        assert!((new.rating - 1464.06).abs() < 0.01, "{:?}", new);
+        assert!((new.deviation - 151.52).abs() < 0.01, "{:?}", new);
+        assert!((new.volatility - 0.05999).abs() < 0.00001, "{:?}", new);
+    }
+
+    #[test]
+    fn single_game_is_a_period_of_one() {
+        let (player, opponent) = (rating(1600.0, 80.0), rating(1450.0, 120.0));
+        assert_eq!(
+            update(player, opponent, 0.0),
+            update_period(player, &[(opponent, 0.0)])
+        );
+        let win = update(player, opponent, 1.0);
+        assert!(win.rating > player.rating && win.deviation < player.deviation);
+    }
+
+    #[test]
+    fn idle_period_only_widens_deviation() {
+        let player = rating(1500.0, 200.0);
+        let idle = update_period(player, &[]);
+        assert_eq!(idle.rating, player.rating);
+        assert!(idle.deviation > player.deviation);
+    }
+}
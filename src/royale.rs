@@ -0,0 +1,707 @@
+//大逃杀：房间登记、开局与倒计时、攻击目标选择、徽章与KO结算，以及每位玩家的对局流
+use crate::spectate::{Feed, SharedHub};
+use crate::storage::{self, SharedStore};
+use crate::{auth, players};
+use rand::Rng;
+use russia_block::engine;
+use russia_block::rblock::royale_event::Payload as RoyalePayload;
+use russia_block::rblock::royale_message::Payload as RoyaleMessagePayload;
+use russia_block::rblock::spectate_event::Payload as SpectatePayload;
+use russia_block::rblock::{
+    Board, GameKind, GameOver, LobbyPlayer, MatchRecord, PlayerBoard, PlayerOut, RoomInfo,
+    RoomState, RoyaleEvent, RoyaleGarbage, RoyaleJoined, RoyaleMessage, RoyaleStart,
+    SpectatedPlayer, Standing, Standings, Targeting,
+};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tonic::{Status, Streaming};
+
+pub const ROYALE_CAPACITY: usize = 8;
+//快速加入的房间满两人后等待更多玩家加入的时间，人满则立即开始
+const ROYALE_START_DELAY: Duration = Duration::from_secs(10);
+//大厅房间全员准备后的倒计时
+const LOBBY_COUNTDOWN: Duration = Duration::from_secs(5);
+//徽章加成：(徽章数, 攻击提高的百分比)，从高到低匹配
+const BADGE_BONUS: [(u32, u32); 4] = [(30, 100), (14, 75), (6, 50), (2, 25)];
+
+type RoyaleSender = mpsc::UnboundedSender<Result<RoyaleEvent, Status>>;
+
+#[derive(Debug)]
+pub struct RoomPlayer {
+    id: u32,
+    //登录玩家的player_id，匿名为空
+    account: String,
+    name: String,
+    //入座时发给该玩家的密钥，离开、准备与接入对局流时核对
+    pub secret: String,
+    //对局流连接，大厅阶段尚未建立
+    tx: Option<RoyaleSender>,
+    pub ready: bool,
+    alive: bool,
+    kos: u32,
+    badges: u32,
+    placement: u32,
+    targeting: Targeting,
+    //最近一次攻击的目标，供"反击攻击者"策略使用
+    targets: Vec<u32>,
+    //最后一个攻击自己的玩家，顶出时记为其KO
+    last_attacker: Option<u32>,
+    height: u32,
+}
+
+impl RoomPlayer {
+    fn new(id: u32, account: String, name: String) -> Self {
+        RoomPlayer {
+            id,
+            account,
+            name,
+            secret: auth::new_secret(),
+            tx: None,
+            ready: false,
+            alive: true,
+            kos: 0,
+            badges: 0,
+            placement: 0,
+            targeting: Targeting::Random,
+            targets: Vec::new(),
+            last_attacker: None,
+            height: 0,
+        }
+    }
+
+    fn send(&self, payload: RoyalePayload) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(Ok(RoyaleEvent {
+                payload: Some(payload),
+            }));
+        }
+    }
+
+    fn connected(&self) -> bool {
+        self.tx.as_ref().is_some_and(|tx| !tx.is_closed())
+    }
+}
+
+#[derive(Debug)]
+pub struct Room {
+    pub id: u32,
+    name: String,
+    capacity: usize,
+    //快速匹配建的房间；大厅中创建的房间只能按房间号加入
+    quick: bool,
+    state: RoomState,
+    //倒计时结束的时刻，仅在Countdown状态下有效
+    deadline: Option<Instant>,
+    players: Vec<RoomPlayer>,
+    hub: SharedHub,
+    //观战频道，对局开始时打开，决出胜者后关闭
+    feed: Option<Feed>,
+    store: SharedStore,
+}
+
+impl Room {
+    pub fn player(&mut self, id: u32) -> Option<&mut RoomPlayer> {
+        self.players.iter_mut().find(|p| p.id == id)
+    }
+
+    fn started(&self) -> bool {
+        self.state == RoomState::Playing
+    }
+
+    //已决出胜者
+    fn finished(&self) -> bool {
+        self.started() && self.alive() <= 1
+    }
+
+    //座位存在且密钥相符
+    pub fn owns_seat(&self, id: u32, secret: &str) -> bool {
+        self.players
+            .iter()
+            .any(|p| p.id == id && p.secret == secret)
+    }
+
+    fn alive(&self) -> usize {
+        self.players.iter().filter(|p| p.alive).count()
+    }
+
+    pub fn info(&self) -> RoomInfo {
+        let countdown = self
+            .deadline
+            .filter(|_| self.state == RoomState::Countdown)
+            .map_or(Duration::ZERO, |deadline| {
+                deadline.saturating_duration_since(Instant::now())
+            });
+        RoomInfo {
+            room_id: self.id,
+            name: self.name.clone(),
+            capacity: self.capacity as u32,
+            state: self.state as i32,
+            players: self
+                .players
+                .iter()
+                .map(|p| LobbyPlayer {
+                    player_id: p.id,
+                    name: p.name.clone(),
+                    ready: p.ready,
+                })
+                .collect(),
+            countdown_ms: countdown.as_millis() as u32,
+        }
+    }
+
+    fn broadcast(&self, payload: RoyalePayload) {
+        for player in self.players.iter() {
+            player.send(payload.clone());
+        }
+    }
+
+    fn broadcast_standings(&self) {
+        let mut players: Vec<Standing> = self
+            .players
+            .iter()
+            .map(|p| Standing {
+                player_id: p.id,
+                name: p.name.clone(),
+                alive: p.alive,
+                kos: p.kos,
+                badges: p.badges,
+                placement: p.placement,
+                targeting: p.targeting as i32,
+            })
+            .collect();
+        //存活者按KO数排在前，出局者按名次排列
+        players.sort_by_key(|p| (!p.alive, p.placement, std::cmp::Reverse(p.kos)));
+        self.broadcast(RoyalePayload::Standings(Standings {
+            players,
+            alive: self.alive() as u32,
+        }));
+    }
+
+    fn start(&mut self) {
+        if self.started() {
+            return;
+        }
+        self.state = RoomState::Playing;
+        self.deadline = None;
+        self.broadcast(RoyalePayload::Start(RoyaleStart {}));
+        self.broadcast_standings();
+        let players = self
+            .players
+            .iter()
+            .map(|p| SpectatedPlayer {
+                player_id: p.id,
+                name: p.name.clone(),
+            })
+            .collect();
+        let senders: Vec<RoyaleSender> = self.players.iter().filter_map(|p| p.tx.clone()).collect();
+        self.feed = Some(Feed::open(
+            &self.hub,
+            GameKind::Royale,
+            self.id,
+            players,
+            move |spectators| {
+                for tx in senders.iter() {
+                    let _ = tx.send(Ok(RoyaleEvent {
+                        payload: Some(RoyalePayload::Spectators(spectators.clone())),
+                    }));
+                }
+            },
+        ));
+        //大厅中入座却没有接入对局流的玩家开局即算顶出
+        let absent: Vec<u32> = self
+            .players
+            .iter()
+            .filter(|p| !p.connected())
+            .map(|p| p.id)
+            .collect();
+        for id in absent {
+            self.top_out(id);
+        }
+    }
+
+    //全员(至少两人)准备后开始倒计时，有人取消准备或离开则回到等待
+    pub fn update_countdown(&mut self) -> Option<Instant> {
+        if self.started() {
+            return None;
+        }
+        if self.players.len() >= 2 && self.players.iter().all(|p| p.ready) {
+            if self.state != RoomState::Countdown {
+                self.state = RoomState::Countdown;
+                self.deadline = Some(Instant::now() + LOBBY_COUNTDOWN);
+                return self.deadline;
+            }
+        } else {
+            self.state = RoomState::Waiting;
+            self.deadline = None;
+        }
+        None
+    }
+
+    //按攻击者的目标策略选出接收垃圾行的玩家
+    fn pick_targets(&self, from: u32) -> Vec<u32> {
+        let others: Vec<&RoomPlayer> = self
+            .players
+            .iter()
+            .filter(|p| p.alive && p.id != from)
+            .collect();
+        if others.is_empty() {
+            return Vec::new();
+        }
+        let targeting = self
+            .players
+            .iter()
+            .find(|p| p.id == from)
+            .map_or(Targeting::Random, |p| p.targeting);
+        let random = || vec![others[rand::thread_rng().gen_range(0, others.len())].id];
+        match targeting {
+            Targeting::Random => random(),
+            Targeting::Attackers => {
+                let attackers: Vec<u32> = others
+                    .iter()
+                    .filter(|p| p.targets.contains(&from))
+                    .map(|p| p.id)
+                    .collect();
+                if attackers.is_empty() {
+                    random()
+                } else {
+                    attackers
+                }
+            }
+            Targeting::Kos => vec![others.iter().max_by_key(|p| p.height).unwrap().id],
+            Targeting::Badges => vec![others.iter().max_by_key(|p| (p.badges, p.kos)).unwrap().id],
+        }
+    }
+
+    //超出攻击表上限的攻击视为非法直接忽略，加成后转发的行数不超过垃圾行上限
+    fn attack(&mut self, from: u32, lines: u32) {
+        if lines > engine::ATTACK_MAX {
+            return;
+        }
+        let badges = match self.player(from) {
+            Some(player) if player.alive => player.badges,
+            _ => return,
+        };
+        let bonus = BADGE_BONUS
+            .iter()
+            .find(|(need, _)| badges >= *need)
+            .map_or(0, |(_, bonus)| *bonus);
+        let lines = lines
+            .saturating_add(lines.saturating_mul(bonus) / 100)
+            .min(engine::GARBAGE_MAX);
+        let targets = self.pick_targets(from);
+        for &id in targets.iter() {
+            if let Some(target) = self.player(id) {
+                target.last_attacker = Some(from);
+                target.send(RoyalePayload::Garbage(RoyaleGarbage { lines, from }));
+            }
+        }
+        if let Some(player) = self.player(from) {
+            player.targets = targets;
+        }
+    }
+
+    fn top_out(&mut self, id: u32) {
+        if self.finished() {
+            return;
+        }
+        let placement = self.alive() as u32;
+        let attacker = match self.player(id) {
+            Some(player) if player.alive => {
+                player.alive = false;
+                player.placement = placement;
+                player.send(RoyalePayload::GameOver(GameOver {
+                    win: false,
+                    placement,
+                    ..Default::default()
+                }));
+                (player.last_attacker, player.badges)
+            }
+            _ => return,
+        };
+        if let Some(feed) = &self.feed {
+            feed.publish(SpectatePayload::Out(PlayerOut {
+                player_id: id,
+                placement,
+            }));
+        }
+        //击杀者获得一个徽章以及被击杀者的全部徽章
+        if let (Some(killer), badges) = attacker {
+            if let Some(killer) = self.player(killer).filter(|p| p.alive) {
+                killer.kos += 1;
+                killer.badges += 1 + badges;
+            }
+        }
+        if self.alive() == 1 {
+            if let Some(winner) = self.players.iter_mut().find(|p| p.alive) {
+                winner.placement = 1;
+                winner.send(RoyalePayload::GameOver(GameOver {
+                    win: true,
+                    placement: 1,
+                    ..Default::default()
+                }));
+                if let Some(feed) = self.feed.take() {
+                    feed.end(winner.name.clone());
+                }
+            }
+            let mut ranked: Vec<&RoomPlayer> = self.players.iter().collect();
+            ranked.sort_by_key(|p| p.placement);
+            self.store.lock().unwrap().add_match(MatchRecord {
+                kind: GameKind::Royale as i32,
+                players: ranked.iter().map(|p| p.name.clone()).collect(),
+                finished_at: storage::unix_now(),
+                player_ids: ranked.iter().map(|p| p.account.clone()).collect(),
+            });
+        }
+        self.broadcast_standings();
+    }
+}
+
+#[derive(Debug)]
+pub enum RoomError {
+    NotFound,
+    Started,
+    Full,
+}
+
+impl From<RoomError> for Status {
+    fn from(e: RoomError) -> Self {
+        match e {
+            RoomError::NotFound => Status::not_found("room not found"),
+            RoomError::Started => Status::failed_precondition("game already started"),
+            RoomError::Full => Status::failed_precondition("room is full"),
+        }
+    }
+}
+
+//服务端的房间登记表，大厅接口与对局流共用
+#[derive(Debug, Default)]
+pub struct RoomRegistry {
+    next_room: u32,
+    next_player: u32,
+    pub rooms: Vec<Room>,
+    hub: SharedHub,
+    store: SharedStore,
+}
+
+pub type SharedRooms = Arc<Mutex<RoomRegistry>>;
+
+impl RoomRegistry {
+    pub fn new(hub: SharedHub, store: SharedStore) -> Self {
+        RoomRegistry {
+            hub,
+            store,
+            ..Default::default()
+        }
+    }
+
+    pub fn room(&mut self, id: u32) -> Option<&mut Room> {
+        self.rooms.iter_mut().find(|r| r.id == id)
+    }
+
+    pub fn create(&mut self, name: String, capacity: usize, quick: bool) -> &mut Room {
+        self.next_room += 1;
+        self.rooms.push(Room {
+            id: self.next_room,
+            name,
+            capacity,
+            quick,
+            state: RoomState::Waiting,
+            deadline: None,
+            players: Vec::new(),
+            hub: self.hub.clone(),
+            feed: None,
+            store: self.store.clone(),
+        });
+        self.rooms.last_mut().unwrap()
+    }
+
+    //按会话令牌的玩家入座，匿名时caller为None
+    #[allow(clippy::result_large_err)]
+    pub fn add_player(
+        &mut self,
+        room_id: u32,
+        caller: Option<String>,
+    ) -> Result<(u32, &mut Room), Status> {
+        let name = players::display_name(&self.store.lock().unwrap(), caller.as_deref())?;
+        self.next_player += 1;
+        let player_id = self.next_player;
+        let room = self.room(room_id).ok_or(RoomError::NotFound)?;
+        if room.state != RoomState::Waiting {
+            return Err(RoomError::Started.into());
+        }
+        if room.players.len() >= room.capacity {
+            return Err(RoomError::Full.into());
+        }
+        room.players
+            .push(RoomPlayer::new(player_id, caller.unwrap_or_default(), name));
+        Ok((player_id, room))
+    }
+
+    //快速加入第一个未开始且未满的快速匹配房间，没有则新建；返回(房间号, 玩家号)
+    #[allow(clippy::result_large_err)]
+    fn quick_join(&mut self, caller: Option<String>) -> Result<(u32, u32), Status> {
+        let room_id = match self
+            .rooms
+            .iter()
+            .find(|r| r.quick && r.state == RoomState::Waiting && r.players.len() < r.capacity)
+        {
+            Some(room) => room.id,
+            None => {
+                self.create("Quick match".to_string(), ROYALE_CAPACITY, true)
+                    .id
+            }
+        };
+        let (player_id, _) = self.add_player(room_id, caller)?;
+        Ok((room_id, player_id))
+    }
+
+    //离开房间：未开始则移出房间，进行中视为顶出，已决出胜者则不影响结果
+    pub fn leave(&mut self, room_id: u32, player_id: u32) {
+        if let Some(room) = self.room(room_id) {
+            if room.started() {
+                room.top_out(player_id);
+                if let Some(player) = room.player(player_id) {
+                    player.tx = None;
+                }
+            } else {
+                room.players.retain(|p| p.id != player_id);
+                room.update_countdown();
+                room.broadcast_standings();
+            }
+        }
+        self.prune();
+    }
+
+    //回收空房间以及无人连接的对局
+    fn prune(&mut self) {
+        self.rooms.retain(|r| {
+            !r.players.is_empty() && (!r.started() || r.players.iter().any(|p| p.connected()))
+        });
+    }
+}
+
+//倒计时结束时若房间仍处于同一轮倒计时则开始对局
+pub fn schedule_start(rooms: SharedRooms, room_id: u32, deadline: Instant) {
+    tokio::spawn(async move {
+        tokio::time::sleep_until(deadline.into()).await;
+        let mut rooms = rooms.lock().unwrap();
+        if let Some(room) = rooms.room(room_id) {
+            if room.state == RoomState::Countdown && room.deadline == Some(deadline) {
+                room.start();
+            }
+        }
+        rooms.prune();
+    });
+}
+
+fn board_height(board: &Board) -> u32 {
+    board
+        .rows
+        .iter()
+        .rposition(|&row| row != 0)
+        .map_or(0, |top| top as u32 + 1)
+}
+
+pub async fn run_royale(
+    rooms: SharedRooms,
+    caller: Option<String>,
+    mut inbound: Streaming<RoyaleMessage>,
+    tx: RoyaleSender,
+) {
+    let join = match inbound.message().await {
+        Ok(Some(RoyaleMessage {
+            payload: Some(RoyaleMessagePayload::Join(join)),
+        })) => join,
+        _ => {
+            let _ = tx.send(Err(Status::invalid_argument("expected join message")));
+            return;
+        }
+    };
+    let (room_id, player_id, countdown) = {
+        let mut rooms = rooms.lock().unwrap();
+        //room_id为0时快速加入，否则凭座位密钥接入大厅中已加入的房间
+        let (room_id, player_id) = if join.room_id == 0 {
+            match rooms.quick_join(caller) {
+                Ok(joined) => joined,
+                Err(status) => {
+                    let _ = tx.send(Err(status));
+                    return;
+                }
+            }
+        } else {
+            (join.room_id, join.player_id)
+        };
+        let room = match rooms.room(room_id) {
+            Some(room) if join.room_id == 0 || room.owns_seat(player_id, &join.seat_secret) => room,
+            _ => {
+                let _ = tx.send(Err(Status::permission_denied("not a member of this room")));
+                return;
+            }
+        };
+        room.player(player_id).unwrap().tx = Some(tx.clone());
+        let _ = tx.send(Ok(RoyaleEvent {
+            payload: Some(RoyalePayload::Joined(RoyaleJoined {
+                room_id,
+                player_id,
+                capacity: room.capacity as u32,
+            })),
+        }));
+        room.broadcast_standings();
+        if room.started() {
+            room.player(player_id)
+                .unwrap()
+                .send(RoyalePayload::Start(RoyaleStart {}));
+        } else if join.room_id == 0 && room.players.len() >= room.capacity {
+            room.start();
+        }
+        (
+            room_id,
+            player_id,
+            join.room_id == 0 && room.players.len() == 2,
+        )
+    };
+    if countdown {
+        let rooms = rooms.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(ROYALE_START_DELAY).await;
+            if let Some(room) = rooms.lock().unwrap().room(room_id) {
+                if room.players.len() >= 2 {
+                    room.start();
+                }
+            }
+        });
+    }
+
+    while let Ok(Some(message)) = inbound.message().await {
+        let payload = match message.payload {
+            Some(payload) => payload,
+            None => continue,
+        };
+        let mut rooms = rooms.lock().unwrap();
+        let room = match rooms.room(room_id) {
+            Some(room) => room,
+            None => break,
+        };
+        match payload {
+            RoyaleMessagePayload::Targeting(targeting) => {
+                if let (Some(player), Some(targeting)) =
+                    (room.player(player_id), Targeting::from_i32(targeting))
+                {
+                    player.targeting = targeting;
+                }
+                room.broadcast_standings();
+            }
+            _ if !room.started() => {}
+            RoyaleMessagePayload::Board(board) => {
+                if let Some(player) = room.player(player_id) {
+                    player.height = board_height(&board);
+                }
+                if let Some(feed) = &room.feed {
+                    feed.publish(SpectatePayload::Board(PlayerBoard {
+                        player_id,
+                        board: Some(board),
+                    }));
+                }
+            }
+            RoyaleMessagePayload::Attack(attack) => room.attack(player_id, attack.lines),
+            RoyaleMessagePayload::TopOut(_) => room.top_out(player_id),
+            RoyaleMessagePayload::Join(_) => {}
+        }
+    }
+    rooms.lock().unwrap().leave(room_id, player_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quick_join_skips_lobby_rooms() {
+        let mut rooms = RoomRegistry::default();
+        let lobby_room = rooms.create("lobby".to_string(), 4, false).id;
+        let (quick_room, _) = rooms.quick_join(None).unwrap();
+        assert_ne!(quick_room, lobby_room);
+        //第二个人进入同一个快速匹配房间
+        let (again, _) = rooms.quick_join(None).unwrap();
+        assert_eq!(again, quick_room);
+        assert_eq!(rooms.room(lobby_room).unwrap().players.len(), 0);
+    }
+
+    //入座并接入对局流，返回该玩家收到的事件
+    fn attach(room: &mut Room, id: u32) -> mpsc::UnboundedReceiver<Result<RoyaleEvent, Status>> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        room.players
+            .push(RoomPlayer::new(id, String::new(), format!("p{}", id)));
+        room.player(id).unwrap().tx = Some(tx);
+        rx
+    }
+
+    fn game_overs(rx: &mut mpsc::UnboundedReceiver<Result<RoyaleEvent, Status>>) -> Vec<bool> {
+        let mut overs = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            if let Some(RoyalePayload::GameOver(over)) = event.unwrap().payload {
+                overs.push(over.win);
+            }
+        }
+        overs
+    }
+
+    #[test]
+    fn absent_seats_are_topped_out_at_start() {
+        let mut rooms = RoomRegistry::default();
+        let room_id = rooms.create("lobby".to_string(), 4, false).id;
+        let room = rooms.room(room_id).unwrap();
+        let mut rx = attach(room, 1);
+        let _ = attach(room, 2);
+        room.players
+            .push(RoomPlayer::new(3, String::new(), "p3".to_string()));
+        room.start();
+        //2号的接收端已丢弃、3号从未接入，1号直接获胜
+        assert_eq!(room.alive(), 1);
+        assert!(room.finished());
+        assert_eq!(game_overs(&mut rx), vec![true]);
+    }
+
+    #[test]
+    fn leave_after_finish_keeps_result() {
+        let mut rooms = RoomRegistry::default();
+        let room_id = rooms.create("lobby".to_string(), 4, false).id;
+        let room = rooms.room(room_id).unwrap();
+        let mut winner = attach(room, 1);
+        let _loser = attach(room, 2);
+        room.start();
+        room.top_out(2);
+        assert_eq!(game_overs(&mut winner), vec![true]);
+        rooms.leave(room_id, 1);
+        assert!(game_overs(&mut winner).is_empty());
+        let stored = rooms.store.lock().unwrap().matches().len();
+        assert_eq!(stored, 1);
+    }
+
+    fn garbage(rx: &mut mpsc::UnboundedReceiver<Result<RoyaleEvent, Status>>) -> Vec<u32> {
+        let mut lines = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            if let Some(RoyalePayload::Garbage(garbage)) = event.unwrap().payload {
+                lines.push(garbage.lines);
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn attack_lines_are_validated_and_capped() {
+        let mut rooms = RoomRegistry::default();
+        let room_id = rooms.create("lobby".to_string(), 4, false).id;
+        let room = rooms.room(room_id).unwrap();
+        let _attacker = attach(room, 1);
+        let mut target = attach(room, 2);
+        room.start();
+        room.attack(1, u32::MAX);
+        assert!(garbage(&mut target).is_empty());
+        //徽章加成后超过垃圾行上限的部分被截掉
+        room.player(1).unwrap().badges = 30;
+        room.attack(1, engine::ATTACK_MAX);
+        assert_eq!(garbage(&mut target), vec![engine::GARBAGE_MAX]);
+    }
+}
@@ -1,18 +1,112 @@
+use auth::TokenKey;
+use limit::RateLimiter;
+use lobby::LobbyService;
+use log::{info, warn};
+use players::PlayerService;
+use rblock::lobby_server::LobbyServer;
+use rblock::players_server::PlayersServer;
 use rblock::score_server::{Score, ScoreServer};
-use rblock::{ScoreRequest, ScoreResponse};
-use std::sync::Mutex;
-use tonic::{transport::Server, Request, Response, Status};
-#[macro_use]
-extern crate lazy_static;
+use rblock::spectator_server::SpectatorServer;
+use rblock::versus_server::VersusServer;
+use rblock::{
+    Leaderboard, LeaderboardEntry, LeaderboardRequest, LeaderboardUpdate, Period, RankRequest,
+    RankResponse, ScoreEntry, SubmitScoreRequest, SubmitScoreResponse, WatchLeaderboardRequest,
+};
+use rblock::{Replay, RoyaleEvent, RoyaleMessage, ScoreRequest, ScoreResponse};
+use royale::{run_royale, RoomRegistry, SharedRooms};
+use russia_block::engine::{self, Engine, Input};
+use russia_block::rblock;
+use spectate::{SharedHub, SpectatorService};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use storage::{LogStorage, MemoryStorage, SharedStore, Storage, Store};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, UnboundedReceiverStream};
+use tokio_stream::Stream;
+use tonic::{transport::Server, Request, Response, Status, Streaming};
+use versus::VersusService;
+
+mod auth;
+mod boards;
+mod config;
+mod limit;
+mod lobby;
+mod players;
+mod rating;
+mod royale;
+mod spectate;
+mod storage;
+mod tls;
+mod versus;
 
-pub mod rblock {
-    tonic::include_proto!("rblock");
-}
 #[derive(Default, Debug)]
-pub struct RussiaBlockService {}
+pub struct RussiaBlockService {
+    rooms: SharedRooms,
+    store: SharedStore,
+    //单次排行榜查询最多返回的条数
+    leaderboard_max: u32,
+}
+
+//排行榜查询未指定条数时的默认值
+const LEADERBOARD_DEFAULT: u32 = 10;
+const BOARD_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+//订阅排行榜时未推送出去的更新条数上限
+const WATCH_BUFFER: usize = 4;
+//录像允许的输入条数上限，正常对局远达不到
+const REPLAY_EVENTS_MAX: usize = 200_000;
+//单人模式没有对手，录像中不应出现垃圾行
+const SOLO_MODES: [&str; 2] = ["single", "training"];
+
+//榜上从第offset名起最多limit条
+fn leaderboard_entries(
+    store: &Store,
+    mode: &str,
+    period: Period,
+    offset: usize,
+    limit: usize,
+) -> Vec<LeaderboardEntry> {
+    store
+        .top(mode, period, offset, limit)
+        .into_iter()
+        .map(|e| LeaderboardEntry {
+            //同分同名次
+            rank: store.rank(mode, period, e.score) as u32 + 1,
+            score: e.score,
+            //改名后显示新名字
+            player: store
+                .profile(&e.player_id)
+                .map_or_else(|| e.player.clone(), |p| p.name.clone()),
+            mode: e.mode.clone(),
+            recorded_at: e.recorded_at,
+            player_id: e.player_id.clone(),
+            pieces: e.pieces,
+            lines: e.lines,
+            duration_ms: e.duration_ms,
+        })
+        .collect()
+}
 
-lazy_static! {
-    static ref SCORES: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+fn leaderboard_update(
+    store: &Store,
+    mode: &str,
+    period: Period,
+    topk: u32,
+    player_id: &str,
+) -> LeaderboardUpdate {
+    let entries = leaderboard_entries(store, mode, period, 0, topk as usize);
+    let best = match player_id.is_empty() {
+        true => None,
+        false => store.best_on_board(player_id, mode, period),
+    };
+    LeaderboardUpdate {
+        scores: entries.iter().map(|e| e.score).collect(),
+        entries,
+        total: store.board_len(mode, period) as u32,
+        resets_at: boards::resets_at(period, storage::unix_now()),
+        rank: best.map_or(0, |e| store.rank(mode, period, e.score) as u32 + 1),
+        best_score: best.map_or(0, |e| e.score),
+    }
 }
 
 #[tonic::async_trait]
@@ -21,34 +115,460 @@ impl Score for RussiaBlockService {
         &self,
         request: Request<ScoreRequest>,
     ) -> Result<Response<ScoreResponse>, Status> {
+        let caller = auth::caller(&request);
         let req = request.into_inner();
-        let mut scores = SCORES.lock().unwrap();
-        scores.push(req.score);
-        scores.sort();
-        scores.reverse();
-        let rank = scores.iter().position(|&x| x == req.score).unwrap();
-
-        let topk = if scores.len() < req.topk as usize {
-            scores.clone()
-        } else {
-            scores[0..req.topk as usize].to_vec()
+        let name = players::display_name(&self.store.lock().unwrap(), caller.as_deref())?;
+        let player_id = caller.unwrap_or_default();
+        let replay = req.replay.map(Arc::new);
+        let verified = match &replay {
+            Some(replay) => match verify_replay_blocking(req.score, replay.clone()).await {
+                Ok(stats) => Some(stats),
+                Err(reason) => {
+                    warn!("rejected score {} from {}: {}", req.score, name, reason);
+                    None
+                }
+            },
+            None => None,
         };
+        let mut store = self.store.lock().unwrap();
+        if let (Some(stats), Some(replay)) = (verified, &replay) {
+            store.add_score(ScoreEntry {
+                player: name,
+                player_id,
+                ..score_entry(replay, stats)
+            });
+        }
+        let rank = store.rank("", Period::AllTime, req.score);
+        let topk = store
+            .top("", Period::AllTime, 0, req.topk as usize)
+            .into_iter()
+            .map(|e| e.score)
+            .collect();
         let response = ScoreResponse {
-            success: true,
+            success: replay.is_none() || verified.is_some(),
             rank: rank as u32,
             scores: topk,
         };
         Ok(Response::new(response))
     }
+
+    async fn submit_score(
+        &self,
+        request: Request<SubmitScoreRequest>,
+    ) -> Result<Response<SubmitScoreResponse>, Status> {
+        let caller = auth::caller(&request);
+        let req = request.into_inner();
+        //成绩归属会话令牌的玩家，不带令牌的只能匿名提交
+        let player_id = match caller {
+            Some(caller) if req.player_id.is_empty() || req.player_id == caller => caller,
+            Some(_) => {
+                return Err(Status::permission_denied(
+                    "player_id does not match session",
+                ))
+            }
+            None if req.player_id.is_empty() => String::new(),
+            None => {
+                return Err(Status::unauthenticated(
+                    "login required to submit as a player",
+                ))
+            }
+        };
+        if req.game_id.is_empty() {
+            return Err(Status::invalid_argument("missing game_id"));
+        }
+        let replay = req
+            .replay
+            .ok_or_else(|| Status::invalid_argument("missing replay"))?;
+        //同一局重复提交(如客户端重试)直接返回首次计入的名次，名次为全部模式的总榜
+        let recorded = |store: &Store, score: u32| SubmitScoreResponse {
+            accepted: true,
+            rank: store.rank("", Period::AllTime, score) as u32 + 1,
+            duplicate: true,
+            reason: String::new(),
+        };
+        //已注册的玩家以登记的名字计入，匿名提交不采用录像中的名字
+        let name = {
+            let store = self.store.lock().unwrap();
+            if let Some(score) = store.game_score(&req.game_id) {
+                return Ok(Response::new(recorded(&store, score)));
+            }
+            players::display_name(&store, Some(player_id.as_str()).filter(|id| !id.is_empty()))?
+        };
+        //重放较慢，不持有锁
+        let replay = Arc::new(replay);
+        let stats = match verify_replay_blocking(replay.score, replay.clone()).await {
+            Ok(stats) => stats,
+            Err(reason) => {
+                warn!("rejected score {} from {}: {}", replay.score, name, reason);
+                return Ok(Response::new(SubmitScoreResponse {
+                    accepted: false,
+                    rank: 0,
+                    duplicate: false,
+                    reason,
+                }));
+            }
+        };
+        let mut store = self.store.lock().unwrap();
+        if let Some(score) = store.game_score(&req.game_id) {
+            return Ok(Response::new(recorded(&store, score)));
+        }
+        store.add_score(ScoreEntry {
+            player: name,
+            game_id: req.game_id,
+            player_id,
+            ..score_entry(&replay, stats)
+        });
+        Ok(Response::new(SubmitScoreResponse {
+            duplicate: false,
+            ..recorded(&store, replay.score)
+        }))
+    }
+
+    async fn get_leaderboard(
+        &self,
+        request: Request<LeaderboardRequest>,
+    ) -> Result<Response<Leaderboard>, Status> {
+        let req = request.into_inner();
+        let period = board_period(req.period)?;
+        let limit = match req.limit {
+            0 => LEADERBOARD_DEFAULT,
+            limit => limit.min(self.leaderboard_max),
+        };
+        let store = self.store.lock().unwrap();
+        Ok(Response::new(Leaderboard {
+            entries: leaderboard_entries(
+                &store,
+                &req.mode,
+                period,
+                req.offset as usize,
+                limit as usize,
+            ),
+            total: store.board_len(&req.mode, period) as u32,
+            resets_at: boards::resets_at(period, storage::unix_now()),
+        }))
+    }
+
+    async fn get_rank(
+        &self,
+        request: Request<RankRequest>,
+    ) -> Result<Response<RankResponse>, Status> {
+        let req = request.into_inner();
+        let period = board_period(req.period)?;
+        let store = self.store.lock().unwrap();
+        Ok(Response::new(RankResponse {
+            rank: store.rank(&req.mode, period, req.score) as u32 + 1,
+            total: store.board_len(&req.mode, period) as u32,
+        }))
+    }
+
+    type WatchLeaderboardStream =
+        Pin<Box<dyn Stream<Item = Result<LeaderboardUpdate, Status>> + Send>>;
+
+    //每次排行榜变化时重新取前topk名，与上次推送的相同则不推送
+    async fn watch_leaderboard(
+        &self,
+        request: Request<WatchLeaderboardRequest>,
+    ) -> Result<Response<Self::WatchLeaderboardStream>, Status> {
+        let caller = auth::caller(&request);
+        let req = request.into_inner();
+        let period = board_period(req.period)?;
+        let topk = match req.topk {
+            0 => LEADERBOARD_DEFAULT,
+            topk => topk.min(self.leaderboard_max),
+        };
+        let player_id = match req.player_id.is_empty() {
+            true => caller.unwrap_or_default(),
+            false => req.player_id,
+        };
+        let store = self.store.clone();
+        let mut changes = store.lock().unwrap().watch_boards();
+        let (tx, rx) = mpsc::channel(WATCH_BUFFER);
+        tokio::spawn(async move {
+            let mut last = None;
+            loop {
+                let update =
+                    leaderboard_update(&store.lock().unwrap(), &req.mode, period, topk, &player_id);
+                if last.as_ref() != Some(&update) {
+                    if tx.send(Ok(update.clone())).await.is_err() {
+                        break;
+                    }
+                    last = Some(update);
+                }
+                tokio::select! {
+                    changed = changes.changed() => if changed.is_err() { break },
+                    _ = tx.closed() => break,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    type RoyaleStream = Pin<Box<dyn Stream<Item = Result<RoyaleEvent, Status>> + Send>>;
+
+    async fn royale(
+        &self,
+        request: Request<Streaming<RoyaleMessage>>,
+    ) -> Result<Response<Self::RoyaleStream>, Status> {
+        let caller = auth::caller(&request);
+        let inbound = request.into_inner();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(run_royale(self.rooms.clone(), caller, inbound, tx));
+        Ok(Response::new(Box::pin(UnboundedReceiverStream::new(rx))))
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn board_period(period: i32) -> Result<Period, Status> {
+    Period::from_i32(period).ok_or_else(|| Status::invalid_argument("unknown period"))
+}
+
+//重放得到的对局统计
+#[derive(Clone, Copy, Debug)]
+struct ReplayStats {
+    pieces: u32,
+    lines: u32,
+    //按各事件的时间间隔累加，不采用录像自报的时长
+    duration_ms: u32,
+}
+
+//计入的玩家由调用方按会话令牌填写
+fn score_entry(replay: &Replay, stats: ReplayStats) -> ScoreEntry {
+    ScoreEntry {
+        score: replay.score,
+        mode: replay.mode.clone(),
+        seed: replay.seed,
+        recorded_at: replay.recorded_at,
+        pieces: stats.pieces,
+        lines: stats.lines,
+        duration_ms: stats.duration_ms,
+        submitted_at: storage::unix_now(),
+        ..Default::default()
+    }
+}
+
+//重放较慢，放到阻塞线程池中进行，不占用异步执行器
+async fn verify_replay_blocking(score: u32, replay: Arc<Replay>) -> Result<ReplayStats, String> {
+    tokio::task::spawn_blocking(move || verify_replay(score, &replay))
+        .await
+        .unwrap_or_else(|e| Err(format!("replay check failed: {}", e)))
+}
+
+//用与客户端共用的规则引擎重放录像，重放得分与上报一致才算有效
+fn verify_replay(score: u32, replay: &Replay) -> Result<ReplayStats, String> {
+    if replay.version != engine::REPLAY_VERSION {
+        return Err(format!("unsupported replay version {}", replay.version));
+    }
+    if replay.score != score {
+        return Err(format!("replay records score {}", replay.score));
+    }
+    //先限制长度再重放，避免超长录像占满重放线程
+    if replay.events.len() > REPLAY_EVENTS_MAX {
+        return Err(format!("replay has more than {} events", REPLAY_EVENTS_MAX));
+    }
+    let duration_ms: u64 = replay.events.iter().map(|e| e.delta_ms as u64).sum();
+    let duration_ms = u32::try_from(duration_ms).map_err(|_| "replay is too long")?;
+    let inputs = replay
+        .events
+        .iter()
+        .map(Input::from_event)
+        .collect::<Option<Vec<_>>>()
+        .ok_or("unknown input kind")?;
+    let solo = SOLO_MODES.contains(&replay.mode.as_str());
+    if solo
+        && inputs
+            .iter()
+            .any(|input| matches!(input, Input::Garbage(_)))
+    {
+        return Err(format!("{} replay has garbage events", replay.mode));
+    }
+    if let Some(Input::Garbage(lines)) = inputs
+        .iter()
+        .find(|input| matches!(input, Input::Garbage(lines) if *lines > engine::GARBAGE_MAX))
+    {
+        return Err(format!("replay has a garbage event of {} lines", lines));
+    }
+    let mut engine = Engine::new(replay.seed);
+    let mut stats = ReplayStats {
+        pieces: 0,
+        lines: 0,
+        duration_ms,
+    };
+    for input in inputs {
+        if let Some(lock) = engine.apply(input) {
+            stats.pieces += 1;
+            stats.lines += lock.lines;
+        }
+    }
+    let simulated = engine.score();
+    if simulated != score {
+        return Err(format!("replay simulates to score {}", simulated));
+    }
+    Ok(stats)
+}
+
+//查询时总是取当期的榜，这里只是定时释放过期的日榜与周榜
+async fn rollover_boards(store: SharedStore) {
+    let mut interval = tokio::time::interval(BOARD_PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let dropped = store.lock().unwrap().prune_boards();
+        if dropped > 0 {
+            info!("rolled over {} expired leaderboards", dropped);
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let addr = "127.0.0.1:8020".parse().unwrap();
-    let rb_service = RussiaBlockService::default();
-    Server::builder()
-        .add_service(ScoreServer::new(rb_service))
+    //server [--config <toml>] [--bind <addr>] [--storage <path|memory>] [--leaderboard-max <n>]
+    //  [--log-level <filter>] [--rate-limit <n/s>] [--rate-burst <n>]
+    //  [--tls-cert <pem> --tls-key <pem> [--tls-client-ca <pem>]] [--print-config]
+    //各项也可写在配置文件或RBLOCK_*环境变量中，--print-config输出合并后的配置
+    //server gen-cert <dir> 生成测试用的自签名证书
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("gen-cert") {
+        let dir = args.nth(1).unwrap_or_else(|| "certs".to_string());
+        tls::generate(dir.as_ref())?;
+        println!("certificates written to {}", dir);
+        return Ok(());
+    }
+    let options = match config::parse(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("invalid configuration:\n{}", e);
+            std::process::exit(2);
+        }
+    };
+    let config = options.config;
+    if options.print_config {
+        print!("{}", toml::to_string(&config)?);
+        return Ok(());
+    }
+    env_logger::Builder::new()
+        .parse_filters(&config.log_level)
+        .init();
+    let addr = config.bind_addr()?;
+    let tls_config = config.tls.server_config()?;
+    let storage_path = config.storage.clone();
+    //令牌签名密钥与日志放在一起，内存存储时每次启动重新生成
+    let (backend, key): (Box<dyn Storage>, TokenKey) = match storage_path.as_str() {
+        "memory" => (Box::new(MemoryStorage), TokenKey::random()),
+        path => (
+            Box::new(LogStorage::open(path)?),
+            TokenKey::load_or_create(format!("{}.key", path))?,
+        ),
+    };
+    let store = Store::open(backend)?;
+    info!(
+        "storage {}: {} scores, {} players, {} matches",
+        storage_path,
+        store.score_count(),
+        store.players().count(),
+        store.matches().len()
+    );
+    let store = Arc::new(Mutex::new(store));
+    tokio::spawn(rollover_boards(store.clone()));
+
+    let hub = SharedHub::default();
+    let rooms = Arc::new(Mutex::new(RoomRegistry::new(hub.clone(), store.clone())));
+    let rb_service = RussiaBlockService {
+        rooms: rooms.clone(),
+        store: store.clone(),
+        leaderboard_max: config.leaderboard_max,
+    };
+    let limiter = RateLimiter::new(&config.rate_limit);
+    let mut builder = Server::builder();
+    if let Some(tls_config) = tls_config {
+        builder = builder.tls_config(tls_config)?;
+        info!(
+            "tls enabled, client certificates {}",
+            match config.tls.client_ca {
+                Some(_) => "required",
+                None => "not required",
+            }
+        );
+    }
+    builder
+        .add_service(ScoreServer::with_interceptor(
+            rb_service,
+            limiter.wrap(auth::interceptor(key.clone())),
+        ))
+        .add_service(PlayersServer::with_interceptor(
+            PlayerService {
+                store: store.clone(),
+                key: key.clone(),
+            },
+            limiter.wrap(auth::interceptor(key.clone())),
+        ))
+        .add_service(VersusServer::with_interceptor(
+            VersusService::new(hub.clone(), store),
+            limiter.wrap(auth::interceptor(key.clone())),
+        ))
+        .add_service(LobbyServer::with_interceptor(
+            LobbyService { rooms },
+            limiter.wrap(auth::interceptor(key)),
+        ))
+        .add_service(SpectatorServer::with_interceptor(
+            SpectatorService { hub },
+            limiter.interceptor(),
+        ))
         .serve(addr)
         .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use russia_block::rblock::InputEvent;
+
+    fn replay(events: Vec<InputEvent>) -> Replay {
+        Replay {
+            version: engine::REPLAY_VERSION,
+            //自报的时长不被采用
+            duration_ms: 1,
+            events,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn replay_duration_comes_from_events() {
+        let events = vec![Input::Left.to_event(400), Input::Right.to_event(600)];
+        let stats = verify_replay(0, &replay(events)).unwrap();
+        assert_eq!(stats.duration_ms, 1000);
+        let overflow = vec![Input::Left.to_event(u32::MAX), Input::Left.to_event(1)];
+        assert!(verify_replay(0, &replay(overflow)).is_err());
+    }
+
+    #[test]
+    fn oversized_replay_is_rejected() {
+        let events = vec![Input::Left.to_event(0); REPLAY_EVENTS_MAX + 1];
+        let reason = verify_replay(0, &replay(events)).unwrap_err();
+        assert!(reason.contains("events"), "{}", reason);
+    }
+
+    #[test]
+    fn oversized_garbage_is_rejected() {
+        let events = vec![Input::Garbage(engine::GARBAGE_MAX + 1).to_event(0)];
+        let reason = verify_replay(0, &replay(events)).unwrap_err();
+        assert!(reason.contains("garbage"), "{}", reason);
+    }
+
+    #[test]
+    fn garbage_is_rejected_in_solo_modes() {
+        let events = vec![Input::Garbage(2).to_event(0)];
+        for mode in SOLO_MODES {
+            let solo = Replay {
+                mode: mode.to_string(),
+                ..replay(events.clone())
+            };
+            assert!(verify_replay(0, &solo).is_err(), "{}", mode);
+        }
+        let versus = Replay {
+            mode: "versus".to_string(),
+            ..replay(events)
+        };
+        assert!(verify_replay(0, &versus).is_ok());
+    }
+}
@@ -0,0 +1,279 @@
+//观战：对局把棋盘等事件发布到频道，观众按各自的延迟收到，防止对局者借观战窥视对手
+use crate::players;
+use crate::rblock::spectate_event::Payload as SpectatePayload;
+use crate::rblock::spectator_server::Spectator;
+use crate::rblock::{
+    GameKind, GameSummary, ListGamesRequest, ListGamesResponse, SpectateEnd, SpectateEvent,
+    SpectateRequest, SpectateStart, SpectatedPlayer, Spectators,
+};
+use std::fmt;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+//观战延迟的默认值与上下限；上限受频道缓存限制，延迟太长时缓存放不下延迟期间的事件
+const DEFAULT_DELAY: Duration = Duration::from_secs(5);
+const MIN_DELAY: Duration = Duration::from_secs(3);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+//频道缓存的事件数，需容纳最长延迟期间所有玩家的棋盘更新(每人每秒10帧，大逃杀最多8人)
+const FEED_CAPACITY: usize = 4096;
+
+//带发布时刻的事件，观众端据此延迟转发
+type Stamped = (Instant, SpectateEvent);
+
+struct Game {
+    kind: GameKind,
+    id: u32,
+    players: Vec<SpectatedPlayer>,
+    spectators: Vec<(u32, String)>,
+    tx: broadcast::Sender<Stamped>,
+    //观众名单变化时通知对局中的玩家
+    notify: Box<dyn Fn(Spectators) + Send>,
+}
+
+impl fmt::Debug for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Game")
+            .field("kind", &self.kind)
+            .field("id", &self.id)
+            .field("players", &self.players)
+            .field("spectators", &self.spectators)
+            .finish()
+    }
+}
+
+impl Game {
+    fn summary(&self) -> GameSummary {
+        GameSummary {
+            kind: self.kind as i32,
+            game_id: self.id,
+            players: self.players.clone(),
+            spectators: self.spectators.len() as u32,
+        }
+    }
+
+    fn spectators_changed(&self) {
+        let spectators = Spectators {
+            names: self
+                .spectators
+                .iter()
+                .map(|(_, name)| name.clone())
+                .collect(),
+        };
+        (self.notify)(spectators.clone());
+        let _ = self.tx.send((
+            Instant::now(),
+            SpectateEvent {
+                payload: Some(SpectatePayload::Spectators(spectators)),
+            },
+        ));
+    }
+}
+
+//正在进行且可观战的对局
+#[derive(Debug, Default)]
+pub struct SpectateHub {
+    next_game: u32,
+    next_spectator: u32,
+    games: Vec<Game>,
+}
+
+pub type SharedHub = Arc<Mutex<SpectateHub>>;
+
+impl SpectateHub {
+    //1v1对战没有房间号，由此分配观战用的对局号
+    pub fn next_game(&mut self) -> u32 {
+        self.next_game += 1;
+        self.next_game
+    }
+
+    fn game(&mut self, kind: GameKind, id: u32) -> Option<&mut Game> {
+        self.games.iter_mut().find(|g| g.kind == kind && g.id == id)
+    }
+
+    fn watch(
+        &mut self,
+        kind: GameKind,
+        id: u32,
+        name: String,
+    ) -> Option<(u32, GameSummary, broadcast::Receiver<Stamped>)> {
+        self.next_spectator += 1;
+        let spectator = self.next_spectator;
+        let game = self.game(kind, id)?;
+        let rx = game.tx.subscribe();
+        game.spectators.push((spectator, name));
+        game.spectators_changed();
+        Some((spectator, game.summary(), rx))
+    }
+
+    fn unwatch(&mut self, kind: GameKind, id: u32, spectator: u32) {
+        if let Some(game) = self.game(kind, id) {
+            game.spectators.retain(|(s, _)| *s != spectator);
+            game.spectators_changed();
+        }
+    }
+}
+
+//对局持有的发布端，关闭或释放时从可观战列表中移除
+#[derive(Debug)]
+pub struct Feed {
+    hub: SharedHub,
+    kind: GameKind,
+    id: u32,
+    tx: broadcast::Sender<Stamped>,
+}
+
+impl Feed {
+    pub fn open(
+        hub: &SharedHub,
+        kind: GameKind,
+        id: u32,
+        players: Vec<SpectatedPlayer>,
+        notify: impl Fn(Spectators) + Send + 'static,
+    ) -> Feed {
+        let (tx, _) = broadcast::channel(FEED_CAPACITY);
+        hub.lock().unwrap().games.push(Game {
+            kind,
+            id,
+            players,
+            spectators: Vec::new(),
+            tx: tx.clone(),
+            notify: Box::new(notify),
+        });
+        Feed {
+            hub: hub.clone(),
+            kind,
+            id,
+            tx,
+        }
+    }
+
+    pub fn publish(&self, payload: SpectatePayload) {
+        //没有观众时发送失败，忽略即可
+        let _ = self.tx.send((
+            Instant::now(),
+            SpectateEvent {
+                payload: Some(payload),
+            },
+        ));
+    }
+
+    //对局结束：通知观众胜者并停止接受新的观众
+    pub fn end(&self, winner: String) {
+        self.publish(SpectatePayload::End(SpectateEnd { winner }));
+        self.close();
+    }
+
+    fn close(&self) {
+        let (kind, id) = (self.kind, self.id);
+        self.hub
+            .lock()
+            .unwrap()
+            .games
+            .retain(|g| g.kind != kind || g.id != id);
+    }
+}
+
+impl Drop for Feed {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn spectate_delay(delay_ms: u32) -> Duration {
+    match delay_ms {
+        0 => DEFAULT_DELAY,
+        ms => Duration::from_millis(ms as u64).clamp(MIN_DELAY, MAX_DELAY),
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct SpectatorService {
+    pub hub: SharedHub,
+}
+
+#[tonic::async_trait]
+impl Spectator for SpectatorService {
+    async fn list_games(
+        &self,
+        _request: Request<ListGamesRequest>,
+    ) -> Result<Response<ListGamesResponse>, Status> {
+        let hub = self.hub.lock().unwrap();
+        Ok(Response::new(ListGamesResponse {
+            games: hub.games.iter().map(Game::summary).collect(),
+        }))
+    }
+
+    type SpectateStream = Pin<Box<dyn Stream<Item = Result<SpectateEvent, Status>> + Send>>;
+
+    async fn spectate(
+        &self,
+        request: Request<SpectateRequest>,
+    ) -> Result<Response<Self::SpectateStream>, Status> {
+        let req = request.into_inner();
+        let kind = GameKind::from_i32(req.kind)
+            .ok_or_else(|| Status::invalid_argument("unknown game kind"))?;
+        let delay = spectate_delay(req.delay_ms);
+        //观众名单会发给对局中的玩家，名字与玩家名同样校验，未给出时为匿名
+        let name = match req.name.trim() {
+            "" => players::ANONYMOUS.to_string(),
+            name => players::normalize_name(name).map_err(Status::invalid_argument)?,
+        };
+        let (spectator, game, mut feed) = self
+            .hub
+            .lock()
+            .unwrap()
+            .watch(kind, req.game_id, name)
+            .ok_or_else(|| Status::not_found("game not found"))?;
+
+        let (tx, rx) = mpsc::channel(64);
+        let hub = self.hub.clone();
+        tokio::spawn(async move {
+            let start = SpectateEvent {
+                payload: Some(SpectatePayload::Start(SpectateStart {
+                    game: Some(game),
+                    delay_ms: delay.as_millis() as u32,
+                })),
+            };
+            if tx.send(Ok(start)).await.is_ok() {
+                loop {
+                    let (at, event) = match feed.recv().await {
+                        Ok(stamped) => stamped,
+                        //跟不上时结束观战，以免观众看到缺帧的对局
+                        Err(RecvError::Lagged(_)) => {
+                            let status = Status::resource_exhausted("spectator fell behind");
+                            let _ = tx.send(Err(status)).await;
+                            break;
+                        }
+                        Err(RecvError::Closed) => break,
+                    };
+                    tokio::time::sleep_until((at + delay).into()).await;
+                    let end = matches!(event.payload, Some(SpectatePayload::End(_)));
+                    if tx.send(Ok(event)).await.is_err() || end {
+                        break;
+                    }
+                }
+            }
+            hub.lock().unwrap().unwatch(kind, req.game_id, spectator);
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_is_clamped() {
+        assert_eq!(spectate_delay(0), DEFAULT_DELAY);
+        assert_eq!(spectate_delay(1), MIN_DELAY);
+        assert_eq!(spectate_delay(10_000), Duration::from_secs(10));
+        assert_eq!(spectate_delay(u32::MAX), MAX_DELAY);
+    }
+}
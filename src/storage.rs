@@ -0,0 +1,572 @@
+//持久化：排行榜、玩家评分与对局记录全部保存在内存中，每次变更再追加一条记录到存储后端，重启时重放
+use crate::boards::{self, Boards};
+use crate::rblock::stored_record::Record;
+use crate::rblock::{
+    Credential, MatchRecord, Period, PlayerProfile, PlayerRecord, ScoreEntry, StoredRecord,
+};
+use log::{error, warn};
+use prost::encoding::decode_varint;
+use prost::{DecodeError, Message};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+pub trait Storage: Send {
+    //按写入顺序读出全部记录
+    fn load(&mut self) -> io::Result<Vec<StoredRecord>>;
+    fn append(&mut self, record: &StoredRecord) -> io::Result<()>;
+}
+
+//不落盘，重启后清空
+pub struct MemoryStorage;
+
+impl Storage for MemoryStorage {
+    fn load(&mut self) -> io::Result<Vec<StoredRecord>> {
+        Ok(Vec::new())
+    }
+
+    fn append(&mut self, _record: &StoredRecord) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+//追加写入的日志文件，每条记录为带长度前缀的protobuf
+pub struct LogStorage {
+    path: PathBuf,
+    file: File,
+    //最后一条完整记录的末尾，追加失败时截回这里
+    len: u64,
+}
+
+impl LogStorage {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(LogStorage { path, file, len })
+    }
+}
+
+//单条记录的长度上限，与gRPC默认的消息大小上限一致
+const RECORD_MAX: u64 = 4 << 20;
+
+//(一条记录的内容, 其后的字节)
+type Split<'a> = (&'a [u8], &'a [u8]);
+
+//切出下一条记录；长度前缀不完整，或长度合理但指向文件末尾之后时为None，即写入中途退出留下的末尾
+fn split_record(bytes: &[u8]) -> Result<Option<Split<'_>>, DecodeError> {
+    let mut rest = bytes;
+    let len = match decode_varint(&mut rest) {
+        Ok(len) => len,
+        //varint最长10字节，每个字节都带后续标记说明是被截断的
+        Err(_) if bytes.len() < 10 && bytes.iter().all(|b| b & 0x80 != 0) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    //超出上限的长度不可能是写了一半的记录，只能是日志损坏
+    if len > RECORD_MAX {
+        return Err(DecodeError::new(format!(
+            "record length {} out of range",
+            len
+        )));
+    }
+    if len > rest.len() as u64 {
+        return Ok(None);
+    }
+    Ok(Some(rest.split_at(len as usize)))
+}
+
+impl Storage for LogStorage {
+    //只截掉不完整的末尾记录；中间的记录损坏时报错，不动文件，以免丢掉其后的数据
+    fn load(&mut self) -> io::Result<Vec<StoredRecord>> {
+        let mut bytes = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut bytes)?;
+        let mut records = Vec::new();
+        let mut rest = &bytes[..];
+        while !rest.is_empty() {
+            let valid = bytes.len() - rest.len();
+            let corrupt = |e: DecodeError| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{}: corrupt record at byte {}: {}",
+                        self.path.display(),
+                        valid,
+                        e
+                    ),
+                )
+            };
+            let torn = match split_record(rest).map_err(corrupt)? {
+                //最后一条记录长度完整但内容解不开，同样按写了一半处理
+                Some((record, tail)) => match StoredRecord::decode(record) {
+                    Ok(record) => {
+                        records.push(record);
+                        rest = tail;
+                        false
+                    }
+                    Err(_) if tail.is_empty() => true,
+                    Err(e) => return Err(corrupt(e)),
+                },
+                None => true,
+            };
+            if torn {
+                warn!(
+                    "{}: dropping incomplete tail at byte {}",
+                    self.path.display(),
+                    valid
+                );
+                self.file.set_len(valid as u64)?;
+                break;
+            }
+        }
+        self.len = (bytes.len() - rest.len()) as u64;
+        Ok(records)
+    }
+
+    //写了一半的记录截掉，以免之后追加的记录接在损坏的字节后面
+    fn append(&mut self, record: &StoredRecord) -> io::Result<()> {
+        let bytes = record.encode_length_delimited_to_vec();
+        let written = self
+            .file
+            .write_all(&bytes)
+            .and_then(|()| self.file.sync_data());
+        match written {
+            Ok(()) => {
+                self.len += bytes.len() as u64;
+                Ok(())
+            }
+            Err(e) => {
+                if let Err(truncate) = self.file.set_len(self.len) {
+                    error!("{}: truncate failed: {}", self.path.display(), truncate);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+//后台写盘线程：记录按提交顺序追加，调用方不在Store的锁内等待磁盘
+struct Writer {
+    tx: Option<mpsc::Sender<StoredRecord>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Writer {
+    fn spawn(mut backend: Box<dyn Storage>) -> io::Result<Self> {
+        let (tx, rx) = mpsc::channel::<StoredRecord>();
+        let thread = thread::Builder::new()
+            .name("storage".to_string())
+            .spawn(move || {
+                //写入失败只记日志，内存中的数据照常更新
+                for record in rx {
+                    if let Err(e) = backend.append(&record) {
+                        error!("storage write failed: {}", e);
+                    }
+                }
+            })?;
+        Ok(Writer {
+            tx: Some(tx),
+            thread: Some(thread),
+        })
+    }
+
+    fn send(&self, record: StoredRecord) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(record);
+        }
+    }
+}
+
+//关闭时等已提交的记录写完
+impl Drop for Writer {
+    fn drop(&mut self) {
+        self.tx.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+pub struct Store {
+    writer: Writer,
+    //按计入顺序保存的全部成绩，各排行榜中为其序号
+    scores: Vec<ScoreEntry>,
+    boards: Boards,
+    //各玩家的成绩序号，按计入顺序
+    by_player: HashMap<String, Vec<usize>>,
+    //已计入的game_id及其分数，用于提交去重
+    games: HashMap<String, u32>,
+    //按player_id登记的评分
+    players: HashMap<String, PlayerRecord>,
+    matches: Vec<MatchRecord>,
+    //按player_id登记的玩家
+    profiles: HashMap<String, PlayerProfile>,
+    credentials: HashMap<String, Credential>,
+    //排行榜每次变化(计入成绩或过期清理)时加一，供订阅者等待
+    revision: watch::Sender<u64>,
+}
+
+pub type SharedStore = Arc<Mutex<Store>>;
+
+impl Store {
+    pub fn open(mut backend: Box<dyn Storage>) -> io::Result<Self> {
+        let records = backend.load()?;
+        let mut store = Store {
+            writer: Writer::spawn(backend)?,
+            scores: Vec::new(),
+            boards: Boards::default(),
+            by_player: HashMap::new(),
+            games: HashMap::new(),
+            players: HashMap::new(),
+            matches: Vec::new(),
+            profiles: HashMap::new(),
+            credentials: HashMap::new(),
+            revision: watch::channel(0).0,
+        };
+        for record in records.into_iter().filter_map(|r| r.record) {
+            store.apply(record);
+        }
+        Ok(store)
+    }
+
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::Score(entry) => {
+                if !entry.game_id.is_empty() {
+                    self.games.insert(entry.game_id.clone(), entry.score);
+                }
+                let index = self.scores.len();
+                self.boards.insert(
+                    index,
+                    entry.score,
+                    &entry.mode,
+                    entry_time(&entry),
+                    unix_now(),
+                );
+                if !entry.player_id.is_empty() {
+                    self.by_player
+                        .entry(entry.player_id.clone())
+                        .or_default()
+                        .push(index);
+                }
+                self.scores.push(entry);
+            }
+            Record::Player(player) => {
+                self.players.insert(player.player_id.clone(), player);
+            }
+            Record::Game(game) => self.matches.push(game),
+            Record::Profile(profile) => {
+                self.profiles.insert(profile.player_id.clone(), profile);
+            }
+            Record::Credential(credential) => {
+                self.credentials
+                    .insert(credential.player_id.clone(), credential);
+            }
+        }
+    }
+
+    //交给写盘线程后立即更新内存中的数据
+    fn write(&mut self, record: Record) {
+        self.writer.send(StoredRecord {
+            record: Some(record.clone()),
+        });
+        self.apply(record);
+    }
+
+    pub fn add_score(&mut self, entry: ScoreEntry) {
+        self.write(Record::Score(entry));
+        self.bump_revision();
+    }
+
+    fn bump_revision(&self) {
+        let revision = *self.revision.borrow() + 1;
+        self.revision.send_replace(revision);
+    }
+
+    //排行榜变化时收到通知
+    pub fn watch_boards(&self) -> watch::Receiver<u64> {
+        self.revision.subscribe()
+    }
+
+    pub fn score_count(&self) -> usize {
+        self.scores.len()
+    }
+
+    //mode为空时为全部模式；按分数从高到低，从第offset名起最多limit条
+    pub fn top(&self, mode: &str, period: Period, offset: usize, limit: usize) -> Vec<&ScoreEntry> {
+        match self.boards.get(mode, period, unix_now()) {
+            Some(board) => board
+                .range(offset, limit)
+                .map(|(_, &index)| &self.scores[index])
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    //该榜上严格高于score的成绩数
+    pub fn rank(&self, mode: &str, period: Period, score: u32) -> usize {
+        self.boards
+            .get(mode, period, unix_now())
+            .map_or(0, |board| board.rank(score))
+    }
+
+    //该榜当期的成绩数
+    pub fn board_len(&self, mode: &str, period: Period) -> usize {
+        self.boards
+            .get(mode, period, unix_now())
+            .map_or(0, |board| board.len())
+    }
+
+    //丢弃已过期的日榜与周榜，返回丢弃的数量
+    pub fn prune_boards(&mut self) -> usize {
+        let dropped = self.boards.prune(unix_now());
+        if dropped > 0 {
+            self.bump_revision();
+        }
+        dropped
+    }
+
+    //该玩家在当期榜上的最好成绩
+    pub fn best_on_board(
+        &self,
+        player_id: &str,
+        mode: &str,
+        period: Period,
+    ) -> Option<&ScoreEntry> {
+        let current = boards::window(period, unix_now());
+        self.history(player_id)
+            .into_iter()
+            .filter(|e| mode.is_empty() || e.mode == mode)
+            .filter(|e| boards::window(period, entry_time(e)) == current)
+            .max_by_key(|e| e.score)
+    }
+
+    //该玩家计入的全部成绩，按计入顺序
+    pub fn history(&self, player_id: &str) -> Vec<&ScoreEntry> {
+        self.by_player.get(player_id).map_or(Vec::new(), |indices| {
+            indices.iter().map(|&index| &self.scores[index]).collect()
+        })
+    }
+
+    //该局已计入时返回其分数
+    pub fn game_score(&self, game_id: &str) -> Option<u32> {
+        self.games.get(game_id).copied()
+    }
+
+    pub fn save_player(&mut self, player: PlayerRecord) {
+        self.write(Record::Player(player));
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = &PlayerRecord> {
+        self.players.values()
+    }
+
+    pub fn add_match(&mut self, game: MatchRecord) {
+        self.write(Record::Game(game));
+    }
+
+    pub fn matches(&self) -> &[MatchRecord] {
+        &self.matches
+    }
+
+    pub fn save_profile(&mut self, profile: PlayerProfile) {
+        self.write(Record::Profile(profile));
+    }
+
+    pub fn profile(&self, player_id: &str) -> Option<&PlayerProfile> {
+        self.profiles.get(player_id)
+    }
+
+    pub fn save_credential(&mut self, credential: Credential) {
+        self.write(Record::Credential(credential));
+    }
+
+    pub fn credential(&self, player_id: &str) -> Option<&Credential> {
+        self.credentials.get(player_id)
+    }
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Store::open(Box::new(MemoryStorage)).unwrap()
+    }
+}
+
+impl fmt::Debug for Store {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Store")
+            .field("scores", &self.scores.len())
+            .field("boards", &self.boards.len())
+            .field("players", &self.players.len())
+            .field("matches", &self.matches.len())
+            .field("profiles", &self.profiles.len())
+            .finish()
+    }
+}
+
+//成绩计入的时间，旧记录没有submitted_at时按录制时间
+pub fn entry_time(entry: &ScoreEntry) -> u64 {
+    match entry.submitted_at {
+        0 => entry.recorded_at,
+        time => time,
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn temp_log() -> PathBuf {
+        static NEXT: AtomicU32 = AtomicU32::new(0);
+        std::env::temp_dir().join(format!(
+            "rblock-storage-{}-{}.log",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ))
+    }
+
+    fn score(score: u32) -> StoredRecord {
+        StoredRecord {
+            record: Some(Record::Score(ScoreEntry {
+                score,
+                player: "p".to_string(),
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn scores(records: &[StoredRecord]) -> Vec<u32> {
+        records
+            .iter()
+            .map(|r| match &r.record {
+                Some(Record::Score(entry)) => entry.score,
+                _ => panic!("not a score"),
+            })
+            .collect()
+    }
+
+    //写入三条记录，返回各条记录的起始位置
+    fn write_three(path: &Path) -> Vec<usize> {
+        let mut log = LogStorage::open(path).unwrap();
+        let mut offsets = Vec::new();
+        for i in 1..=3 {
+            offsets.push(std::fs::metadata(path).unwrap().len() as usize);
+            log.append(&score(i * 100)).unwrap();
+        }
+        offsets
+    }
+
+    #[test]
+    fn torn_tail_is_truncated() {
+        let path = temp_log();
+        write_three(&path);
+        let complete = std::fs::metadata(&path).unwrap().len();
+        //第四条只写了一半
+        let torn = score(400).encode_length_delimited_to_vec();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&torn[..torn.len() / 2]).unwrap();
+
+        let mut log = LogStorage::open(&path).unwrap();
+        assert_eq!(scores(&log.load().unwrap()), vec![100, 200, 300]);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), complete);
+        log.append(&score(500)).unwrap();
+        let mut log = LogStorage::open(&path).unwrap();
+        assert_eq!(scores(&log.load().unwrap()), vec![100, 200, 300, 500]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn torn_length_prefix_is_truncated() {
+        let path = temp_log();
+        write_three(&path);
+        let complete = std::fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0x80]).unwrap();
+
+        let mut log = LogStorage::open(&path).unwrap();
+        assert_eq!(log.load().unwrap().len(), 3);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), complete);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_middle_record_is_an_error() {
+        let path = temp_log();
+        let offsets = write_three(&path);
+        let mut bytes = std::fs::read(&path).unwrap();
+        let len = bytes.len();
+        //把第二条记录的第一个字段标签改成不存在的wire type
+        bytes[offsets[1] + 1] = 0x07;
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mut log = LogStorage::open(&path).unwrap();
+        let error = log.load().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(std::fs::read(&path).unwrap().len(), len);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn oversized_length_in_the_middle_is_an_error() {
+        let path = temp_log();
+        let offsets = write_three(&path);
+        let bytes = std::fs::read(&path).unwrap();
+        //第二条记录的长度前缀换成超出上限的值，指向文件末尾之后
+        let mut corrupt = bytes[..offsets[1]].to_vec();
+        prost::encoding::encode_varint(RECORD_MAX + 1, &mut corrupt);
+        corrupt.extend_from_slice(&bytes[offsets[1] + 1..]);
+        std::fs::write(&path, &corrupt).unwrap();
+
+        let mut log = LogStorage::open(&path).unwrap();
+        let error = log.load().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(std::fs::read(&path).unwrap(), corrupt);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn undecodable_last_record_is_truncated() {
+        let path = temp_log();
+        let offsets = write_three(&path);
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[offsets[2] + 1] = 0x07;
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mut log = LogStorage::open(&path).unwrap();
+        assert_eq!(scores(&log.load().unwrap()), vec![100, 200]);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), offsets[2] as u64);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn store_writes_are_flushed_on_drop() {
+        let path = temp_log();
+        let mut store = Store::open(Box::new(LogStorage::open(&path).unwrap())).unwrap();
+        store.add_score(ScoreEntry {
+            score: 100,
+            ..Default::default()
+        });
+        drop(store);
+        let store = Store::open(Box::new(LogStorage::open(&path).unwrap())).unwrap();
+        assert_eq!(store.score_count(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
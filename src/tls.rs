@@ -0,0 +1,80 @@
+//可选的TLS：按证书与私钥路径加载服务端身份，给出客户端CA时要求客户端出示证书(mTLS)。
+//gen-cert生成一套自签名CA及其签发的服务端、客户端证书，供本地测试使用
+use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa, RcgenError, SanType};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use tonic::transport::{Certificate as CaCertificate, Identity, ServerTlsConfig};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsOptions {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub client_ca: Option<PathBuf>,
+}
+
+impl TlsOptions {
+    //未给出证书时不启用TLS
+    pub fn server_config(&self) -> Result<Option<ServerTlsConfig>, Box<dyn Error>> {
+        let (cert, key) = match (&self.cert, &self.key) {
+            (None, None) if self.client_ca.is_none() => return Ok(None),
+            (Some(cert), Some(key)) => (cert, key),
+            _ => return Err("cert and key must be given together".into()),
+        };
+        let identity = Identity::from_pem(read(cert)?, read(key)?);
+        let mut config = ServerTlsConfig::new().identity(identity);
+        if let Some(ca) = &self.client_ca {
+            config = config.client_ca_root(CaCertificate::from_pem(read(ca)?));
+        }
+        Ok(Some(config))
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+//在dir下写入ca.pem、server.pem/server.key与client.pem/client.key，服务端证书对localhost与127.0.0.1有效
+pub fn generate(dir: &Path) -> Result<(), Box<dyn Error>> {
+    std::fs::create_dir_all(dir)?;
+    let mut params = CertificateParams::new(Vec::new());
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "russia_block test CA");
+    let ca = Certificate::from_params(params)?;
+    std::fs::write(dir.join("ca.pem"), ca.serialize_pem()?)?;
+
+    let server = leaf(
+        "localhost",
+        vec![
+            SanType::DnsName("localhost".to_string()),
+            SanType::IpAddress(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        ],
+    )?;
+    std::fs::write(
+        dir.join("server.pem"),
+        server.serialize_pem_with_signer(&ca)?,
+    )?;
+    std::fs::write(dir.join("server.key"), server.serialize_private_key_pem())?;
+
+    let client = leaf(
+        "russia_block client",
+        vec![SanType::DnsName("client".to_string())],
+    )?;
+    std::fs::write(
+        dir.join("client.pem"),
+        client.serialize_pem_with_signer(&ca)?,
+    )?;
+    std::fs::write(dir.join("client.key"), client.serialize_private_key_pem())?;
+    Ok(())
+}
+
+fn leaf(name: &str, names: Vec<SanType>) -> Result<Certificate, RcgenError> {
+    let mut params = CertificateParams::new(Vec::new());
+    params.distinguished_name.push(DnType::CommonName, name);
+    params.subject_alt_names = names;
+    Certificate::from_params(params)
+}
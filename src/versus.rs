@@ -0,0 +1,560 @@
+//一对一对战：按评分匹配对手，转发双方的棋盘、攻击与落定，结束后更新评分并记下对局
+use crate::auth;
+use crate::rating::{self, Rating};
+use crate::spectate::{Feed, SharedHub};
+use crate::storage::{self, SharedStore, Store};
+use log::warn;
+use russia_block::engine;
+use russia_block::rblock::spectate_event::Payload as SpectatePayload;
+use russia_block::rblock::versus_event::Payload as EventPayload;
+use russia_block::rblock::versus_message::Payload as MessagePayload;
+use russia_block::rblock::versus_server::Versus;
+use russia_block::rblock::{
+    self, Attack, GameKind, GameOver, MatchRecord, Matched, PlayerBoard, PlayerRecord, RatingBoard,
+    SpectatedPlayer, VersusEvent, VersusMessage,
+};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::oneshot;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status, Streaming};
+
+type EventSender = mpsc::Sender<Result<VersusEvent, Status>>;
+//每位对战玩家未发出的事件上限，满了说明客户端跟不上
+const VERSUS_BUFFER: usize = 64;
+//对局结果须送达，客户端在此时间内仍腾不出位置则断开
+const RESULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+//对战中的一方
+#[derive(Clone)]
+struct Seat {
+    player_id: String,
+    //登记的显示名
+    name: String,
+    tx: EventSender,
+    //断开该座位的事件流，只触发一次
+    kick: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+}
+
+impl Seat {
+    //返回座位与发给该客户端的事件流
+    fn new(player_id: String, name: String) -> (Seat, SeatStream) {
+        let (tx, rx) = mpsc::channel(VERSUS_BUFFER);
+        let (kick, kicked) = oneshot::channel();
+        let seat = Seat {
+            player_id,
+            name,
+            tx,
+            kick: Arc::new(Mutex::new(Some(kick))),
+        };
+        let stream = SeatStream {
+            events: ReceiverStream::new(rx),
+            kicked: Some(kicked),
+            ended: false,
+        };
+        (seat, stream)
+    }
+
+    //不等待接收慢的客户端，队列已满时断开该座位并返回false
+    fn send(&self, payload: EventPayload) -> bool {
+        let event = Ok(VersusEvent {
+            payload: Some(payload),
+        });
+        match self.tx.try_send(event) {
+            Err(TrySendError::Full(_)) => {
+                self.disconnect();
+                false
+            }
+            _ => true,
+        }
+    }
+
+    //对局结果等待队列腾出位置再发，超时则断开
+    async fn send_result(&self, payload: EventPayload) {
+        if self.kick.lock().unwrap().is_none() {
+            return;
+        }
+        let event = Ok(VersusEvent {
+            payload: Some(payload),
+        });
+        if tokio::time::timeout(RESULT_TIMEOUT, self.tx.send(event))
+            .await
+            .is_err()
+        {
+            self.disconnect();
+        }
+    }
+
+    fn disconnect(&self) {
+        if let Some(kick) = self.kick.lock().unwrap().take() {
+            let _ = kick.send(());
+        }
+    }
+}
+
+//发给客户端的事件流，座位被断开后以错误结束，不再发出积压的事件
+struct SeatStream {
+    events: ReceiverStream<Result<VersusEvent, Status>>,
+    kicked: Option<oneshot::Receiver<()>>,
+    ended: bool,
+}
+
+impl Stream for SeatStream {
+    type Item = Result<VersusEvent, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.ended {
+            return Poll::Ready(None);
+        }
+        if let Some(kicked) = self.kicked.as_mut() {
+            match Pin::new(kicked).poll(cx) {
+                Poll::Ready(Ok(())) => {
+                    self.ended = true;
+                    self.events.close();
+                    let status = Status::resource_exhausted("client fell behind");
+                    return Poll::Ready(Some(Err(status)));
+                }
+                //座位都已释放，之后只需发完剩下的事件
+                Poll::Ready(Err(_)) => self.kicked = None,
+                Poll::Pending => {}
+            }
+        }
+        Pin::new(&mut self.events).poll_next(cx)
+    }
+}
+
+//配对结果：对手座位、己方在观战频道中的编号、双方共享的结束标记与观战频道
+struct Pairing {
+    opponent: Seat,
+    seat: u32,
+    //任一方结束后置位，避免重复判定胜负
+    finished: Arc<AtomicBool>,
+    feed: Arc<Feed>,
+}
+
+//初始允许的评分差，以及每等待一秒放宽的幅度
+const MATCH_WINDOW: f64 = 100.0;
+const MATCH_WINDOW_GROWTH: f64 = 50.0;
+const MATCH_INTERVAL: Duration = Duration::from_secs(1);
+const RATING_BOARD_SIZE: usize = 10;
+
+//匹配队列中的玩家：其座位、评分以及用于交付对手座位的通道
+struct Queued {
+    seat: Seat,
+    rating: f64,
+    since: Instant,
+    notify: oneshot::Sender<Pairing>,
+}
+
+impl Queued {
+    fn window(&self, now: Instant) -> f64 {
+        MATCH_WINDOW + MATCH_WINDOW_GROWTH * now.duration_since(self.since).as_secs_f64()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct PlayerRating {
+    rating: Rating,
+    games: u32,
+    wins: u32,
+}
+
+impl PlayerRating {
+    fn to_proto(self, name: &str) -> rblock::PlayerRating {
+        rblock::PlayerRating {
+            name: name.to_string(),
+            rating: self.rating.rating.round() as u32,
+            deviation: self.rating.deviation.round() as u32,
+            games: self.games,
+            wins: self.wins,
+        }
+    }
+
+    fn to_record(self, seat: &Seat) -> PlayerRecord {
+        PlayerRecord {
+            name: seat.name.clone(),
+            player_id: seat.player_id.clone(),
+            rating: self.rating.rating,
+            deviation: self.rating.deviation,
+            volatility: self.rating.volatility,
+            games: self.games,
+            wins: self.wins,
+        }
+    }
+
+    fn from_record(record: &PlayerRecord) -> Self {
+        PlayerRating {
+            rating: Rating {
+                rating: record.rating,
+                deviation: record.deviation,
+                volatility: record.volatility,
+            },
+            games: record.games,
+            wins: record.wins,
+        }
+    }
+}
+
+//按player_id记录的评分，名字只用于显示
+#[derive(Debug, Default)]
+pub struct Ratings {
+    players: HashMap<String, PlayerRating>,
+}
+
+impl Ratings {
+    fn load(store: &Store) -> Self {
+        Ratings {
+            players: store
+                .players()
+                .map(|p| (p.player_id.clone(), PlayerRating::from_record(p)))
+                .collect(),
+        }
+    }
+
+    fn get(&self, player_id: &str) -> PlayerRating {
+        self.players.get(player_id).copied().unwrap_or_default()
+    }
+
+    //记录一局对战结果，返回(胜者, 败者)的新评分
+    fn record(&mut self, winner: &str, loser: &str) -> (PlayerRating, PlayerRating) {
+        let (old_winner, old_loser) = (self.get(winner), self.get(loser));
+        let new_winner = PlayerRating {
+            rating: rating::update(old_winner.rating, old_loser.rating, 1.0),
+            games: old_winner.games + 1,
+            wins: old_winner.wins + 1,
+        };
+        let new_loser = PlayerRating {
+            rating: rating::update(old_loser.rating, old_winner.rating, 0.0),
+            games: old_loser.games + 1,
+            wins: old_loser.wins,
+        };
+        self.players.insert(winner.to_string(), new_winner);
+        self.players.insert(loser.to_string(), new_loser);
+        (new_winner, new_loser)
+    }
+
+    //显示玩家当前登记的名字
+    fn board(&self, store: &Store) -> RatingBoard {
+        let mut players: Vec<(&String, &PlayerRating)> = self.players.iter().collect();
+        players.sort_by(|a, b| b.1.rating.rating.total_cmp(&a.1.rating.rating));
+        RatingBoard {
+            players: players
+                .into_iter()
+                .take(RATING_BOARD_SIZE)
+                .map(|(player_id, rating)| {
+                    let name = store.profile(player_id).map_or("", |p| p.name.as_str());
+                    rating.to_proto(name)
+                })
+                .collect(),
+        }
+    }
+}
+
+//按评分匹配对手：评分相近的玩家两两配对，等待越久允许的分差越大
+#[derive(Clone, Default)]
+struct Matchmaker {
+    queue: Arc<Mutex<Vec<Queued>>>,
+    ratings: Arc<Mutex<Ratings>>,
+    hub: SharedHub,
+    store: SharedStore,
+}
+
+impl Matchmaker {
+    fn pair(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.retain(|q| !q.seat.tx.is_closed());
+        queue.sort_by(|a, b| a.rating.total_cmp(&b.rating));
+        let now = Instant::now();
+        let mut i = 0;
+        while i + 1 < queue.len() {
+            let window = queue[i].window(now).max(queue[i + 1].window(now));
+            //同一账号从两个客户端加入时不与自己配对
+            let same = queue[i].seat.player_id == queue[i + 1].seat.player_id;
+            if !same && queue[i + 1].rating - queue[i].rating <= window {
+                let b = queue.remove(i + 1);
+                let a = queue.remove(i);
+                let finished = Arc::new(AtomicBool::new(false));
+                let game_id = self.hub.lock().unwrap().next_game();
+                let players = vec![
+                    SpectatedPlayer {
+                        player_id: 0,
+                        name: a.seat.name.clone(),
+                    },
+                    SpectatedPlayer {
+                        player_id: 1,
+                        name: b.seat.name.clone(),
+                    },
+                ];
+                let senders = [a.seat.tx.clone(), b.seat.tx.clone()];
+                let feed = Arc::new(Feed::open(
+                    &self.hub,
+                    GameKind::Versus,
+                    game_id,
+                    players,
+                    move |spectators| {
+                        for tx in senders.iter() {
+                            let _ = tx.try_send(Ok(VersusEvent {
+                                payload: Some(EventPayload::Spectators(spectators.clone())),
+                            }));
+                        }
+                    },
+                ));
+                let _ = a.notify.send(Pairing {
+                    opponent: b.seat.clone(),
+                    seat: 0,
+                    finished: finished.clone(),
+                    feed: feed.clone(),
+                });
+                let _ = b.notify.send(Pairing {
+                    opponent: a.seat,
+                    seat: 1,
+                    finished,
+                    feed,
+                });
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    //后台定时重新匹配，使等待中的玩家随时间放宽分差
+    fn spawn(&self) {
+        let matchmaker = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(MATCH_INTERVAL);
+            loop {
+                interval.tick().await;
+                matchmaker.pair();
+            }
+        });
+    }
+
+    //结算一局：更新双方评分并通知结果
+    async fn finish(&self, winner: &Seat, loser: &Seat, feed: &Feed) {
+        feed.end(winner.name.clone());
+        let (old_winner, old_loser, (new_winner, new_loser), board) = {
+            let mut ratings = self.ratings.lock().unwrap();
+            let mut store = self.store.lock().unwrap();
+            let old = (
+                ratings.get(&winner.player_id),
+                ratings.get(&loser.player_id),
+            );
+            let new = ratings.record(&winner.player_id, &loser.player_id);
+            store.save_player(new.0.to_record(winner));
+            store.save_player(new.1.to_record(loser));
+            store.add_match(MatchRecord {
+                kind: GameKind::Versus as i32,
+                players: vec![winner.name.clone(), loser.name.clone()],
+                finished_at: storage::unix_now(),
+                player_ids: vec![winner.player_id.clone(), loser.player_id.clone()],
+            });
+            (old.0, old.1, new, ratings.board(&store))
+        };
+        let change = |old: PlayerRating, new: PlayerRating| {
+            (new.rating.rating.round() - old.rating.rating.round()) as i32
+        };
+        loser
+            .send_result(EventPayload::GameOver(GameOver {
+                win: false,
+                placement: 2,
+                rating: new_loser.rating.rating.round() as u32,
+                rating_change: change(old_loser, new_loser),
+            }))
+            .await;
+        winner
+            .send_result(EventPayload::GameOver(GameOver {
+                win: true,
+                placement: 1,
+                rating: new_winner.rating.rating.round() as u32,
+                rating_change: change(old_winner, new_winner),
+            }))
+            .await;
+        loser.send(EventPayload::Ratings(board.clone()));
+        winner.send(EventPayload::Ratings(board));
+    }
+
+    async fn run_seat(self, me: Seat, mut inbound: Streaming<VersusMessage>) {
+        //第一条消息必须是加入请求
+        match inbound.message().await {
+            Ok(Some(VersusMessage {
+                payload: Some(MessagePayload::Join(_)),
+            })) => {}
+            _ => {
+                let _ = me
+                    .tx
+                    .send(Err(Status::invalid_argument("expected join message")))
+                    .await;
+                return;
+            }
+        }
+
+        let rating = self
+            .ratings
+            .lock()
+            .unwrap()
+            .get(&me.player_id)
+            .rating
+            .rating;
+        let (notify, rx) = oneshot::channel();
+        self.queue.lock().unwrap().push(Queued {
+            seat: me.clone(),
+            rating,
+            since: Instant::now(),
+            notify,
+        });
+        self.pair();
+        let Pairing {
+            opponent,
+            seat,
+            finished,
+            feed,
+        } = match rx.await {
+            Ok(pairing) => pairing,
+            Err(_) => return,
+        };
+
+        let (opponent_rating, board) = {
+            let ratings = self.ratings.lock().unwrap();
+            let board = ratings.board(&self.store.lock().unwrap());
+            (ratings.get(&opponent.player_id).rating.rating, board)
+        };
+        me.send(EventPayload::Matched(Matched {
+            opponent: opponent.name.clone(),
+            rating: rating.round() as u32,
+            opponent_rating: opponent_rating.round() as u32,
+        }));
+        me.send(EventPayload::Ratings(board));
+
+        //转发棋盘和方块事件给对手，直到一方顶出
+        loop {
+            let payload = match inbound.message().await {
+                Ok(Some(VersusMessage {
+                    payload: Some(payload),
+                })) => payload,
+                Ok(Some(_)) => continue,
+                _ => break,
+            };
+            let kept_up = match payload {
+                MessagePayload::Board(board) => {
+                    feed.publish(SpectatePayload::Board(PlayerBoard {
+                        player_id: seat,
+                        board: Some(board.clone()),
+                    }));
+                    opponent.send(EventPayload::OpponentBoard(board))
+                }
+                MessagePayload::Locked(locked) => {
+                    opponent.send(EventPayload::OpponentLocked(locked))
+                }
+                //不信任客户端上报的行数，按攻击表的上限截断
+                MessagePayload::Attack(attack) => opponent.send(EventPayload::Garbage(Attack {
+                    lines: attack.lines.min(engine::ATTACK_MAX),
+                })),
+                MessagePayload::TopOut(_) => {
+                    if !finished.swap(true, Ordering::SeqCst) {
+                        self.finish(&opponent, &me, &feed).await;
+                    }
+                    true
+                }
+                MessagePayload::Join(_) => true,
+            };
+            //对手积压的事件已满，已被断开并判负，不让慢的一方拖住另一方
+            if !kept_up && !finished.swap(true, Ordering::SeqCst) {
+                warn!("{} fell behind, ending the match", opponent.name);
+                self.finish(&me, &opponent, &feed).await;
+            }
+        }
+        //中途断开视为认输
+        if !finished.swap(true, Ordering::SeqCst) {
+            self.finish(&opponent, &me, &feed).await;
+        }
+    }
+}
+
+pub struct VersusService {
+    matchmaker: Matchmaker,
+}
+
+impl VersusService {
+    pub fn new(hub: SharedHub, store: SharedStore) -> Self {
+        let ratings = Ratings::load(&store.lock().unwrap());
+        let matchmaker = Matchmaker {
+            hub,
+            ratings: Arc::new(Mutex::new(ratings)),
+            store,
+            ..Default::default()
+        };
+        matchmaker.spawn();
+        VersusService { matchmaker }
+    }
+}
+
+#[tonic::async_trait]
+impl Versus for VersusService {
+    type PlayStream = Pin<Box<dyn Stream<Item = Result<VersusEvent, Status>> + Send>>;
+
+    //评分按会话令牌的玩家记录，匿名不能参加
+    async fn play(
+        &self,
+        request: Request<Streaming<VersusMessage>>,
+    ) -> Result<Response<Self::PlayStream>, Status> {
+        let player_id =
+            auth::caller(&request).ok_or_else(|| Status::unauthenticated("login required"))?;
+        let name = self
+            .matchmaker
+            .store
+            .lock()
+            .unwrap()
+            .profile(&player_id)
+            .ok_or_else(|| Status::not_found("unknown player_id"))?
+            .name
+            .clone();
+        let inbound = request.into_inner();
+        let (me, events) = Seat::new(player_id, name);
+        tokio::spawn(self.matchmaker.clone().run_seat(me, inbound));
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use russia_block::rblock::Board;
+    use tokio_stream::StreamExt;
+
+    fn board() -> EventPayload {
+        EventPayload::OpponentBoard(Board::default())
+    }
+
+    #[tokio::test]
+    async fn full_queue_disconnects_the_seat() {
+        let (seat, mut events) = Seat::new("a".to_string(), "a".to_string());
+        for _ in 0..VERSUS_BUFFER {
+            assert!(seat.send(board()));
+        }
+        //队列满时立即返回false，事件流以错误结束而不是发完积压的事件
+        assert!(!seat.send(board()));
+        let status = events.next().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert!(events.next().await.is_none());
+        //已断开的座位不再等待发送结果
+        seat.send_result(board()).await;
+    }
+
+    #[test]
+    fn closed_stream_is_not_falling_behind() {
+        let (seat, events) = Seat::new("a".to_string(), "a".to_string());
+        //对方已断开不算跟不上，由其自己的连接判负
+        drop(events);
+        for _ in 0..=VERSUS_BUFFER {
+            assert!(seat.send(board()));
+        }
+    }
+}